@@ -33,6 +33,11 @@ pub const BPS_DENOM: u64 = 10_000;
 pub const MAX_TITLE_LEN: usize = 64;
 pub const MAX_META_LEN: usize = 128;
 
+/// `Config.paused` bit, numbered consistently with cyrus-vault (bits 0-2) and
+/// cyrus-tresor (bits 3-4).
+pub const PAUSE_DONATIONS: u8 = 1 << 5;
+pub const PAUSE_ALL: u8 = PAUSE_DONATIONS;
+
 #[program]
 pub mod cyrus_fundraise {
     use super::*;
@@ -47,6 +52,46 @@ pub mod cyrus_fundraise {
         Ok(())
     }
 
+    /// Authority-only. Starts a handover to `new_authority`, which must sign
+    /// `accept_authority` to complete it; proposing `Pubkey::default()` cancels.
+    pub fn propose_authority(ctx: Context<UpdateConfig>, new_authority: Pubkey) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        cfg.pending_authority = new_authority;
+        emit!(AuthorityProposed { authority: cfg.authority, pending_authority: new_authority });
+        Ok(())
+    }
+
+    /// Signed by the proposed authority; completes the handover.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        let previous = cfg.authority;
+        cfg.authority = cfg.pending_authority;
+        cfg.pending_authority = Pubkey::default();
+        emit!(AuthorityTransferred { previous, authority: cfg.authority });
+        Ok(())
+    }
+
+    /// Authority-only. Every donation after this pays its fee to the new
+    /// collector. Zero address rejected, as in `initialize`.
+    pub fn set_fee_collector(ctx: Context<UpdateConfig>, fee_collector: Pubkey) -> Result<()> {
+        require!(fee_collector != Pubkey::default(), FundError::ZeroFeeCollector);
+        let cfg = &mut ctx.accounts.config;
+        let previous = cfg.fee_collector;
+        cfg.fee_collector = fee_collector;
+        emit!(FeeCollectorUpdated { previous, fee_collector });
+        Ok(())
+    }
+
+    /// Authority-only. Replaces the pause bitmask wholesale. Donations are the
+    /// only class: the program holds nothing, so there is no outflow to keep
+    /// open.
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, FundError::InvalidPauseFlags);
+        ctx.accounts.config.paused = paused;
+        emit!(PauseUpdated { paused });
+        Ok(())
+    }
+
     /// `createCampaign(recipient, token, goal, title, metaURI, listed)`.
     pub fn create_campaign(
         ctx: Context<CreateCampaign>,
@@ -87,6 +132,7 @@ pub mod cyrus_fundraise {
 
     /// `donateNative(id)`. Non-custodial: both legs settle in this instruction.
     pub fn donate_sol(ctx: Context<DonateSol>, amount: u64) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_DONATIONS)?;
         require!(amount > 0, FundError::ZeroAmount);
         {
             let c = &ctx.accounts.campaign;
@@ -149,6 +195,12 @@ pub mod cyrus_fundraise {
     }
 }
 
+/// `set_paused` gate for `donate_sol`.
+fn require_active(config: &Config, class: u8) -> Result<()> {
+    require!(config.paused & class == 0, FundError::Paused);
+    Ok(())
+}
+
 /// `_feeFor`: 0.1% of the amount, floored at the campaign's `minFee`.
 pub fn fee_for(amount: u64, min_fee: u64) -> Result<u64> {
     let pct = amount
@@ -164,8 +216,12 @@ pub fn fee_for(amount: u64, min_fee: u64) -> Result<u64> {
 #[derive(InitSpace)]
 pub struct Config {
     pub authority: Pubkey,
+    /// Set by `propose_authority`, cleared by `accept_authority`.
+    pub pending_authority: Pubkey,
     pub fee_collector: Pubkey,
     pub next_id: u64,
+    /// `PAUSE_*` bitmask; a set bit halts that instruction class.
+    pub paused: u8,
     pub bump: u8,
 }
 
@@ -200,6 +256,22 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(address = config.authority @ FundError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(address = config.pending_authority @ FundError::Unauthorized)]
+    pub pending_authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
 #[derive(Accounts)]
 #[instruction(id: u64)]
 pub struct CreateCampaign<'info> {
@@ -261,6 +333,29 @@ pub struct Donation {
     pub raised: u64,
 }
 
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct FeeCollectorUpdated {
+    pub previous: Pubkey,
+    pub fee_collector: Pubkey,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub paused: u8,
+}
+
 #[error_code]
 pub enum FundError {
     #[msg("Fee collector cannot be the zero address")]
@@ -287,6 +382,10 @@ pub enum FundError {
     MathOverflow,
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("This instruction class is paused")]
+    Paused,
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
 }
 
 // ---------------------------------------------------------------- tests
@@ -17,15 +17,44 @@
 //!     cluster, giving the same "this proof is not valid elsewhere" property.
 //!   * The EVM version reverts if `zkVerifier != address(0)`; v1 here simply
 //!     has no verifier field. A v2 would add a separate instruction.
+//!   * Tree mode (native SOL) is the storage a v2 verifier needs, not yet a
+//!     more private pool. `init_tree` gives a bucket a `CommitmentTree`,
+//!     `commit_to_tree` appends each commitment as a leaf of an incremental
+//!     Merkle tree, and `reveal_from_tree` spends against one of the last
+//!     `ROOT_HISTORY_SIZE` roots through a `[b"nullifier", tree,
+//!     nullifier_hash]` PDA. But the reveal carries the preimage and the leaf
+//!     index in the clear, so anyone can recompute the leaf and match it to
+//!     its `TreeDeposit`: linkability is exactly v1's, and so is the epoch
+//!     gate. Only a zk membership proof against `root`, replacing
+//!     `merkle_root` and the plaintext preimage, would change that.
+//!   * Native SOL pools (`mint == Pubkey::default()`) use `commit_to_pool` /
+//!     `reveal_from_pool`; SPL and Token-2022 pools use the `_token` variants.
+//!     The fee is SOL either way, as the EVM fee is always native.
+//!   * Custody is per pool: each `Pool` has a `[b"pool_vault", mint]` PDA that
+//!     holds a SOL pool's lamports, or owns a token pool's ATA. Fees sit in
+//!     the `FeePot` account itself, never alongside deposits, and
+//!     `Pool.outstanding` tracks what the pool owes unrevealed commitments.
+//!   * Relayed reveals: a depositor may bind a `relayer_fee` (and optionally
+//!     a relayer key) into the commitment via `bind_relayer`, so a third-party
+//!     `caller` can submit the reveal for an empty `withdraw_to` wallet and be
+//!     paid out of the bucket. With no fee and no relayer the hash is the
+//!     plain v1 commitment.
 //!
 //! PRIVACY CAVEAT, unchanged from the EVM original and worth restating: this is
 //! k-anonymity within an epoch+bucket cohort, NOT cryptographic anonymity. An
 //! observer watching a bucket with only one participant in an epoch can link
-//! commit to reveal. The UI must keep saying so.
+//! commit to reveal. The UI must keep saying so, for tree mode too.
 
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::keccak;
 use anchor_lang::system_program;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    onchain::invoke_transfer_checked,
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 
 declare_id!("9rZHsP3T2n1symzKxyqzE6Ah7VTvTRcvw24DZXjFpDd7");
 
@@ -33,6 +62,22 @@ declare_id!("9rZHsP3T2n1symzKxyqzE6Ah7VTvTRcvw24DZXjFpDd7");
 pub const EPOCH_LENGTH: i64 = 3600;
 /// Mirrors the per-token bucket schedule; capped so the account stays bounded.
 pub const MAX_BUCKETS: usize = 8;
+/// Mirrors the EVM `_checkAndUpdateRateLimit` constants; the defaults for
+/// `Config.rate_quota` / `Config.rate_window_secs`.
+pub const MAX_TRANSACTIONS_PER_MINUTE: u32 = 1000;
+pub const RATE_LIMIT_WINDOW_SECONDS: i64 = 60;
+
+/// `Config.paused` bits, numbered consistently with cyrus-vault (bits 0-2)
+/// and cyrus-fundraise (bit 5).
+pub const PAUSE_COMMITS: u8 = 1 << 3;
+pub const PAUSE_REVEALS: u8 = 1 << 4;
+pub const PAUSE_ALL: u8 = PAUSE_COMMITS | PAUSE_REVEALS;
+
+/// Tree mode: 2^20 leaves per bucket, as in Tornado Cash.
+pub const TREE_DEPTH: usize = 20;
+/// How many recent roots a `reveal_from_tree` may prove against, so a proof
+/// built just before someone else's commit still lands.
+pub const ROOT_HISTORY_SIZE: usize = 30;
 
 #[program]
 pub mod cyrus_tresor {
@@ -48,20 +93,105 @@ pub mod cyrus_tresor {
         cfg.authority = ctx.accounts.authority.key();
         cfg.fee_collector = fee_collector;
         cfg.fee_lamports = fee_lamports;
+        cfg.rate_window_secs = RATE_LIMIT_WINDOW_SECONDS;
+        cfg.rate_quota = MAX_TRANSACTIONS_PER_MINUTE;
         cfg.bump = ctx.bumps.config;
+        ctx.accounts.fee_pot.bump = ctx.bumps.fee_pot;
+        Ok(())
+    }
+
+    /// Authority-only. Names a successor; the handover completes only when
+    /// that key signs `accept_authority`. `Pubkey::default()` cancels.
+    pub fn propose_authority(ctx: Context<UpdateConfig>, new_authority: Pubkey) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        cfg.pending_authority = new_authority;
+        emit!(AuthorityProposed { authority: cfg.authority, pending_authority: new_authority });
+        Ok(())
+    }
+
+    /// Signed by the proposed authority; completes the handover.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        let previous = cfg.authority;
+        cfg.authority = cfg.pending_authority;
+        cfg.pending_authority = Pubkey::default();
+        emit!(AuthorityTransferred { previous, authority: cfg.authority });
+        Ok(())
+    }
+
+    /// Authority-only. Redirects `collect_fees`. Fees already accrued in the
+    /// pot go to whichever collector is configured when they are swept.
+    pub fn set_fee_collector(ctx: Context<UpdateConfig>, fee_collector: Pubkey) -> Result<()> {
+        require!(fee_collector != Pubkey::default(), TresorError::InvalidFeeCollector);
+        let cfg = &mut ctx.accounts.config;
+        let previous = cfg.fee_collector;
+        cfg.fee_collector = fee_collector;
+        emit!(FeeCollectorUpdated { previous, fee_collector });
+        Ok(())
+    }
+
+    /// Authority-only. Replaces the pause bitmask wholesale. Classes are
+    /// independent, so new commits can be stopped while outstanding
+    /// commitments stay revealable.
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, TresorError::InvalidPauseFlags);
+        ctx.accounts.config.paused = paused;
+        emit!(PauseUpdated { paused });
+        Ok(())
+    }
+
+    /// Authority-only. `quota` commits per depositor per `window_secs`, and at
+    /// most `cohort_cap` commits per bucket per epoch (0 = uncapped).
+    pub fn set_rate_limits(
+        ctx: Context<UpdateConfig>,
+        window_secs: i64,
+        quota: u32,
+        cohort_cap: u32,
+    ) -> Result<()> {
+        require!(window_secs > 0 && quota > 0, TresorError::InvalidRateLimit);
+        let cfg = &mut ctx.accounts.config;
+        cfg.rate_window_secs = window_secs;
+        cfg.rate_quota = quota;
+        cfg.cohort_cap = cohort_cap;
+        emit!(RateLimitsUpdated { window_secs, quota, cohort_cap });
         Ok(())
     }
 
     /// Registers the fixed denominations for a mint. Mirrors the EVM
     /// constructor's `_bucketSchedules`. `Pubkey::default()` == native SOL.
+    /// The first call for a mint also creates the pool's custody PDA.
+    /// Schedules are append-only: later calls must repeat the existing sizes
+    /// and may only add new ones, so a bucket index never changes meaning.
     pub fn set_buckets(ctx: Context<SetBuckets>, mint: Pubkey, sizes: Vec<u64>) -> Result<()> {
-        require!(!sizes.is_empty(), TresorError::EmptyBucketSchedule);
-        require!(sizes.len() <= MAX_BUCKETS, TresorError::TooManyBuckets);
-        require!(sizes.iter().all(|s| *s > 0), TresorError::ZeroBucketSize);
         let pool = &mut ctx.accounts.pool;
+        pool.extend_sizes(sizes)?;
         pool.mint = mint;
-        pool.sizes = sizes;
         pool.bump = ctx.bumps.pool;
+        ctx.accounts.pool_vault.bump = ctx.bumps.pool_vault;
+        Ok(())
+    }
+
+    /// Authority-only. Stops new commits into a bucket for good. Outstanding
+    /// commitments stay revealable at the amount they locked in.
+    pub fn retire_bucket(ctx: Context<RetireBucket>, bucket_idx: u8) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!((bucket_idx as usize) < pool.sizes.len(), TresorError::BadBucketIndex);
+        pool.retired[bucket_idx as usize] = true;
+        emit!(BucketRetired { mint: pool.mint, bucket_idx });
+        Ok(())
+    }
+
+    /// Authority-only. Opens tree mode for one bucket of a SOL pool. The
+    /// bucket size is snapshotted into the tree; every leaf pays exactly that.
+    pub fn init_tree(ctx: Context<InitTree>, bucket_idx: u8) -> Result<()> {
+        let amount = ctx.accounts.pool.open_bucket(bucket_idx)?;
+        let tree = &mut ctx.accounts.tree;
+        tree.mint = ctx.accounts.pool.mint;
+        tree.bucket_idx = bucket_idx;
+        tree.amount = amount;
+        tree.reset();
+        tree.bump = ctx.bumps.tree;
+        emit!(TreeInitialized { mint: tree.mint, bucket_idx, amount, root: tree.root() });
         Ok(())
     }
 
@@ -75,15 +205,18 @@ pub mod cyrus_tresor {
         commitment: [u8; 32],
         bucket_idx: u8,
     ) -> Result<()> {
-        let pool = &ctx.accounts.pool;
-        let size = *pool
-            .sizes
-            .get(bucket_idx as usize)
-            .ok_or(TresorError::BadBucketIndex)?;
+        require_active(&ctx.accounts.config, PAUSE_COMMITS)?;
+        let clock = Clock::get()?;
+        ctx.accounts.user_stats.bump = ctx.bumps.user_stats;
+        rate_limit(&ctx.accounts.config, &mut ctx.accounts.user_stats, &clock)?;
+        let deposit_epoch = clock.unix_timestamp / EPOCH_LENGTH;
+        let pool = &mut ctx.accounts.pool;
+        let size = pool.open_bucket(bucket_idx)?;
+        pool.count_commit(deposit_epoch, bucket_idx, ctx.accounts.config.cohort_cap)?;
 
-        let fee = ctx.accounts.config.fee_lamports;
-        let total = size.checked_add(fee).ok_or(TresorError::MathOverflow)?;
+        pool.outstanding = pool.outstanding.checked_add(size).ok_or(TresorError::MathOverflow)?;
 
+        let fee = ctx.accounts.config.fee_lamports;
         system_program::transfer(
             CpiContext::new(
                 ctx.accounts.system_program.to_account_info(),
@@ -92,19 +225,88 @@ pub mod cyrus_tresor {
                     to: ctx.accounts.pool_vault.to_account_info(),
                 },
             ),
-            total,
+            size,
         )?;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.fee_pot.to_account_info(),
+                },
+            ),
+            fee,
+        )?;
+
+        let mint = ctx.accounts.pool.mint;
+        let c = &mut ctx.accounts.commitment_account;
+        c.hash = commitment;
+        c.deposit_epoch = deposit_epoch;
+        c.mint = mint;
+        c.bucket_idx = bucket_idx;
+        c.amount = size;
+        c.spent = false;
+        c.bump = ctx.bumps.commitment_account;
+
+        let pot = &mut ctx.accounts.fee_pot;
+        pot.accrued = pot.accrued.checked_add(fee).ok_or(TresorError::MathOverflow)?;
 
+        emit!(PoolDeposit { commitment, mint, bucket_idx, deposit_epoch });
+        Ok(())
+    }
+
+    /// `commitToPool` for an SPL or Token-2022 pool. The bucket size moves
+    /// into the pool's token custody; the fee is still paid in SOL. For a
+    /// transfer-hook mint, `remaining_accounts` carries the hook's extra
+    /// accounts.
+    pub fn commit_to_pool_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, CommitToPoolToken<'info>>,
+        commitment: [u8; 32],
+        bucket_idx: u8,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_COMMITS)?;
+        inspect_mint(&ctx.accounts.mint.to_account_info())?;
         let clock = Clock::get()?;
-        let mint = pool.mint;
+        ctx.accounts.user_stats.bump = ctx.bumps.user_stats;
+        rate_limit(&ctx.accounts.config, &mut ctx.accounts.user_stats, &clock)?;
+        let deposit_epoch = clock.unix_timestamp / EPOCH_LENGTH;
+        let pool = &mut ctx.accounts.pool;
+        let size = pool.open_bucket(bucket_idx)?;
+        pool.count_commit(deposit_epoch, bucket_idx, ctx.accounts.config.cohort_cap)?;
+        pool.outstanding = pool.outstanding.checked_add(size).ok_or(TresorError::MathOverflow)?;
+
+        let fee = ctx.accounts.config.fee_lamports;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.fee_pot.to_account_info(),
+                },
+            ),
+            fee,
+        )?;
+        invoke_transfer_checked(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.depositor_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.pool_token_account.to_account_info(),
+            ctx.accounts.depositor.to_account_info(),
+            ctx.remaining_accounts,
+            size,
+            ctx.accounts.mint.decimals,
+            &[],
+        )?;
+
+        let mint = ctx.accounts.mint.key();
         let c = &mut ctx.accounts.commitment_account;
         c.hash = commitment;
-        c.deposit_epoch = clock.unix_timestamp / EPOCH_LENGTH;
+        c.deposit_epoch = deposit_epoch;
         c.mint = mint;
         c.bucket_idx = bucket_idx;
+        c.amount = size;
         c.spent = false;
         c.bump = ctx.bumps.commitment_account;
-        let deposit_epoch = c.deposit_epoch;
 
         let pot = &mut ctx.accounts.fee_pot;
         pot.accrued = pot.accrued.checked_add(fee).ok_or(TresorError::MathOverflow)?;
@@ -117,41 +319,81 @@ pub mod cyrus_tresor {
     ///
     /// Recomputes the commitment from the preimage. Any tampering — above all
     /// with `withdraw_to`, the MEV-redirect target — yields a different hash,
-    /// which no longer matches the stored one.
+    /// which no longer matches the stored one. The same holds for
+    /// `relayer_fee`: `caller` is paid the fee the depositor bound in, and if
+    /// a relayer was named only that key can make the hash match.
     pub fn reveal_from_pool(
         ctx: Context<RevealFromPool>,
         secret: [u8; 32],
         user_salt: [u8; 32],
         bucket_idx: u8,
+        relayer_fee: u64,
     ) -> Result<()> {
-        {
-            let c = &ctx.accounts.commitment_account;
-            require!(!c.spent, TresorError::AlreadySpent);
-            require!(c.bucket_idx == bucket_idx, TresorError::BucketMismatch);
-
-            // Epoch separation IS the privacy property: a same-epoch reveal
-            // would link deposit and withdrawal directly.
-            let clock = Clock::get()?;
-            let now_epoch = clock.unix_timestamp / EPOCH_LENGTH;
-            require!(now_epoch > c.deposit_epoch, TresorError::SameEpoch);
-
-            let expected = compute_commitment(
-                &secret,
-                &user_salt,
-                &ctx.accounts.withdraw_to.key(),
-                &c.mint,
-                bucket_idx,
-                &crate::ID,
-            );
-            require!(expected == c.hash, TresorError::CommitmentMismatch);
+        require_active(&ctx.accounts.config, PAUSE_REVEALS)?;
+        verify_reveal(
+            &ctx.accounts.commitment_account,
+            &secret,
+            &user_salt,
+            &ctx.accounts.withdraw_to.key(),
+            bucket_idx,
+            relayer_fee,
+            &ctx.accounts.caller.key(),
+        )?;
+
+        // Pay what was locked in at commit, whatever the schedule says now.
+        let size = ctx.accounts.commitment_account.amount;
+        let pool = &mut ctx.accounts.pool;
+        pool.outstanding = pool.outstanding.checked_sub(size).ok_or(TresorError::CustodyShortfall)?;
+
+        // Mark spent BEFORE moving value.
+        let (hash, mint) = {
+            let c = &mut ctx.accounts.commitment_account;
+            c.spent = true;
+            (c.hash, c.mint)
+        };
+
+        let vault = ctx.accounts.pool_vault.to_account_info();
+        if relayer_fee > 0 {
+            pay_out(&vault, &ctx.accounts.caller.to_account_info(), relayer_fee)?;
         }
+        pay_out(&vault, &ctx.accounts.withdraw_to.to_account_info(), size - relayer_fee)?;
 
-        let size = *ctx
-            .accounts
-            .pool
-            .sizes
-            .get(bucket_idx as usize)
-            .ok_or(TresorError::BadBucketIndex)?;
+        emit!(PoolReveal {
+            commitment: hash,
+            mint,
+            bucket_idx,
+            withdraw_to: ctx.accounts.withdraw_to.key(),
+            relayer_fee,
+        });
+        Ok(())
+    }
+
+    /// `revealFromPool` for an SPL or Token-2022 pool: pays the committed amount
+    /// from the pool's token custody into a token account owned by
+    /// `withdraw_to`. Transfer-hook extra accounts go in `remaining_accounts`.
+    /// A relayer fee is paid in the pool's token to `caller_token_account`.
+    pub fn reveal_from_pool_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, RevealFromPoolToken<'info>>,
+        secret: [u8; 32],
+        user_salt: [u8; 32],
+        bucket_idx: u8,
+        relayer_fee: u64,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_REVEALS)?;
+        verify_reveal(
+            &ctx.accounts.commitment_account,
+            &secret,
+            &user_salt,
+            &ctx.accounts.withdraw_to.key(),
+            bucket_idx,
+            relayer_fee,
+            &ctx.accounts.caller.key(),
+        )?;
+
+        // Pay what was locked in at commit, whatever the schedule says now.
+        let size = ctx.accounts.commitment_account.amount;
+        let pool = &mut ctx.accounts.pool;
+        pool.outstanding = pool.outstanding.checked_sub(size).ok_or(TresorError::CustodyShortfall)?;
 
         // Mark spent BEFORE moving value.
         let (hash, mint) = {
@@ -160,14 +402,160 @@ pub mod cyrus_tresor {
             (c.hash, c.mint)
         };
 
-        **ctx.accounts.pool_vault.to_account_info().try_borrow_mut_lamports()? -= size;
-        **ctx.accounts.withdraw_to.to_account_info().try_borrow_mut_lamports()? += size;
+        let seeds: &[&[u8]] = &[b"pool_vault", mint.as_ref(), &[ctx.accounts.pool_vault.bump]];
+        if relayer_fee > 0 {
+            let caller_token_account = ctx
+                .accounts
+                .caller_token_account
+                .as_ref()
+                .ok_or(TresorError::RelayerAccountMissing)?;
+            invoke_transfer_checked(
+                &ctx.accounts.token_program.key(),
+                ctx.accounts.pool_token_account.to_account_info(),
+                ctx.accounts.mint.to_account_info(),
+                caller_token_account.to_account_info(),
+                ctx.accounts.pool_vault.to_account_info(),
+                ctx.remaining_accounts,
+                relayer_fee,
+                ctx.accounts.mint.decimals,
+                &[seeds],
+            )?;
+        }
+        invoke_transfer_checked(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.pool_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.withdraw_to_token_account.to_account_info(),
+            ctx.accounts.pool_vault.to_account_info(),
+            ctx.remaining_accounts,
+            size - relayer_fee,
+            ctx.accounts.mint.decimals,
+            &[seeds],
+        )?;
 
         emit!(PoolReveal {
             commitment: hash,
             mint,
             bucket_idx,
             withdraw_to: ctx.accounts.withdraw_to.key(),
+            relayer_fee,
+        });
+        Ok(())
+    }
+
+    /// Tree-mode `commit_to_pool`: same fee, rate limit, cohort cap and custody, but
+    /// the commitment, bound to its deposit epoch by `tree_leaf`, becomes the
+    /// next leaf of the bucket's `CommitmentTree` rather than an account of its
+    /// own. `TreeDeposit` carries what clients need to rebuild the tree and
+    /// their path.
+    pub fn commit_to_tree(ctx: Context<CommitToTree>, commitment: [u8; 32]) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_COMMITS)?;
+        let clock = Clock::get()?;
+        ctx.accounts.user_stats.bump = ctx.bumps.user_stats;
+        rate_limit(&ctx.accounts.config, &mut ctx.accounts.user_stats, &clock)?;
+        let bucket_idx = ctx.accounts.tree.bucket_idx;
+        let size = ctx.accounts.tree.amount;
+        let pool = &mut ctx.accounts.pool;
+        pool.open_bucket(bucket_idx)?;
+        let deposit_epoch = clock.unix_timestamp / EPOCH_LENGTH;
+        pool.count_commit(deposit_epoch, bucket_idx, ctx.accounts.config.cohort_cap)?;
+        pool.outstanding = pool.outstanding.checked_add(size).ok_or(TresorError::MathOverflow)?;
+
+        let fee = ctx.accounts.config.fee_lamports;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.pool_vault.to_account_info(),
+                },
+            ),
+            size,
+        )?;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.fee_pot.to_account_info(),
+                },
+            ),
+            fee,
+        )?;
+
+        let tree = &mut ctx.accounts.tree;
+        let leaf_index = tree.insert(tree_leaf(&commitment, deposit_epoch))?;
+
+        let pot = &mut ctx.accounts.fee_pot;
+        pot.accrued = pot.accrued.checked_add(fee).ok_or(TresorError::MathOverflow)?;
+
+        emit!(TreeDeposit {
+            commitment,
+            mint: tree.mint,
+            bucket_idx,
+            deposit_epoch,
+            leaf_index,
+            root: tree.root(),
+        });
+        Ok(())
+    }
+
+    /// Tree-mode reveal. The leaf is the v1 commitment (relayer binding
+    /// included) tagged with `proof.deposit_epoch`, and must hash up `proof`
+    /// to a recent `root`. As in v1, the reveal must come in a later epoch.
+    /// Double spends are stopped by `init` on the nullifier PDA. The preimage
+    /// and `proof.leaf_index` are public, so this reveal is as linkable to its
+    /// deposit as a v1 one; `TreeReveal` reports the leaf outright.
+    pub fn reveal_from_tree(
+        ctx: Context<RevealFromTree>,
+        secret: [u8; 32],
+        user_salt: [u8; 32],
+        relayer_fee: u64,
+        nullifier_hash: [u8; 32],
+        root: [u8; 32],
+        proof: MerkleProof,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_REVEALS)?;
+        require!(
+            nullifier_hash == compute_nullifier(&secret, &user_salt),
+            TresorError::NullifierMismatch
+        );
+        let tree = &ctx.accounts.tree;
+        require!(tree.is_known_root(&root), TresorError::UnknownRoot);
+        // The epoch is bound into the leaf, so the proof check below vouches
+        // for it.
+        let now_epoch = Clock::get()?.unix_timestamp / EPOCH_LENGTH;
+        require!(now_epoch > proof.deposit_epoch, TresorError::SameEpoch);
+
+        let withdraw_to = ctx.accounts.withdraw_to.key();
+        let base = compute_commitment(&secret, &user_salt, &withdraw_to, &tree.mint, tree.bucket_idx, &crate::ID);
+        let leaf = |relayer: &Pubkey| tree_leaf(&bind_relayer(base, relayer_fee, relayer), proof.deposit_epoch);
+        require!(
+            merkle_root(leaf(&Pubkey::default()), &proof)? == root
+                || merkle_root(leaf(&ctx.accounts.caller.key()), &proof)? == root,
+            TresorError::InvalidMerkleProof
+        );
+        let (mint, bucket_idx, size) = (tree.mint, tree.bucket_idx, tree.amount);
+        require!(relayer_fee < size, TresorError::RelayerFeeTooHigh);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.outstanding = pool.outstanding.checked_sub(size).ok_or(TresorError::CustodyShortfall)?;
+        ctx.accounts.nullifier.bump = ctx.bumps.nullifier;
+
+        let vault = ctx.accounts.pool_vault.to_account_info();
+        if relayer_fee > 0 {
+            pay_out(&vault, &ctx.accounts.caller.to_account_info(), relayer_fee)?;
+        }
+        pay_out(&vault, &ctx.accounts.withdraw_to.to_account_info(), size - relayer_fee)?;
+
+        emit!(TreeReveal {
+            nullifier_hash,
+            mint,
+            bucket_idx,
+            leaf_index: proof.leaf_index,
+            deposit_epoch: proof.deposit_epoch,
+            withdraw_to,
+            relayer_fee,
         });
         Ok(())
     }
@@ -176,12 +564,102 @@ pub mod cyrus_tresor {
         let amount = ctx.accounts.fee_pot.accrued;
         require!(amount > 0, TresorError::NothingToCollect);
         ctx.accounts.fee_pot.accrued = 0;
-        **ctx.accounts.pool_vault.to_account_info().try_borrow_mut_lamports()? -= amount;
-        **ctx.accounts.fee_collector.to_account_info().try_borrow_mut_lamports()? += amount;
+        pay_out(
+            &ctx.accounts.fee_pot.to_account_info(),
+            &ctx.accounts.fee_collector.to_account_info(),
+            amount,
+        )?;
         Ok(())
     }
 }
 
+/// The checks every reveal shares: unspent, right bucket, a later epoch than
+/// the deposit, a preimage that hashes to the stored commitment, and a relayer
+/// fee that leaves something for `withdraw_to`. A relayed commitment matches
+/// either with `caller` as the named relayer or with any-relayer bound in.
+fn verify_reveal(
+    c: &Commitment,
+    secret: &[u8; 32],
+    user_salt: &[u8; 32],
+    withdraw_to: &Pubkey,
+    bucket_idx: u8,
+    relayer_fee: u64,
+    caller: &Pubkey,
+) -> Result<()> {
+    require!(!c.spent, TresorError::AlreadySpent);
+    require!(c.bucket_idx == bucket_idx, TresorError::BucketMismatch);
+
+    // Epoch separation IS the privacy property: a same-epoch reveal
+    // would link deposit and withdrawal directly.
+    let now_epoch = Clock::get()?.unix_timestamp / EPOCH_LENGTH;
+    require!(now_epoch > c.deposit_epoch, TresorError::SameEpoch);
+
+    let base = compute_commitment(secret, user_salt, withdraw_to, &c.mint, bucket_idx, &crate::ID);
+    require!(
+        bind_relayer(base, relayer_fee, &Pubkey::default()) == c.hash
+            || bind_relayer(base, relayer_fee, caller) == c.hash,
+        TresorError::CommitmentMismatch
+    );
+    require!(relayer_fee < c.amount, TresorError::RelayerFeeTooHigh);
+    Ok(())
+}
+
+/// Refuses Token-2022 mints a fixed-denomination pool cannot honour: a
+/// transfer fee would make deposits and payouts differ from the bucket size,
+/// a permanent delegate could pull custody, a non-transferable mint could
+/// never be paid out, and confidential transfers hide amounts.
+fn inspect_mint(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    for extension in state.get_extension_types()? {
+        if matches!(
+            extension,
+            ExtensionType::TransferFeeConfig
+                | ExtensionType::PermanentDelegate
+                | ExtensionType::NonTransferable
+                | ExtensionType::ConfidentialTransferMint
+        ) {
+            return err!(TresorError::UnsupportedMint);
+        }
+    }
+    Ok(())
+}
+
+/// `set_paused` gate; `commit_to_pool` and `reveal_from_pool` call it first.
+fn require_active(config: &Config, class: u8) -> Result<()> {
+    require!(config.paused & class == 0, TresorError::Paused);
+    Ok(())
+}
+
+/// Mirrors `_checkAndUpdateRateLimit`: a fixed-window quota per depositor.
+fn rate_limit(config: &Config, stats: &mut Account<UserStats>, clock: &Clock) -> Result<()> {
+    let window = clock.unix_timestamp - clock.unix_timestamp.rem_euclid(config.rate_window_secs);
+    if stats.window != window {
+        stats.window = window;
+        stats.count = 0;
+    }
+    require!(stats.count < config.rate_quota, TresorError::RateLimitExceeded);
+    stats.count += 1;
+    Ok(())
+}
+
+/// The only way lamports leave a `PoolVault` or the `FeePot`. Both are
+/// program-owned, so the debit is direct, and neither drops below its
+/// rent-exempt minimum: a payout that would need to is paying out money the
+/// account does not hold.
+fn pay_out(vault: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let floor = Rent::get()?.minimum_balance(vault.data_len());
+    let remaining = vault.lamports().checked_sub(amount).ok_or(TresorError::CustodyShortfall)?;
+    require!(remaining >= floor, TresorError::CustodyShortfall);
+    **vault.try_borrow_mut_lamports()? = remaining;
+    let credited = to.lamports().checked_add(amount).ok_or(TresorError::MathOverflow)?;
+    **to.try_borrow_mut_lamports()? = credited;
+    Ok(())
+}
+
 /// The EVM side hashes `abi.encode(secret, userSalt, withdrawTo, token,
 /// bucketIdx, address(this), block.chainid)`. Same fields, same order; the
 /// chain id is replaced by the program id (see the module docs).
@@ -204,14 +682,86 @@ pub fn compute_commitment(
     .0
 }
 
+/// Binds a relayer fee and, optionally, the only key allowed to reveal
+/// (`Pubkey::default()` lets any caller relay). The depositor commits to the
+/// result, so a relayer can neither raise its fee nor take someone else's.
+/// With no fee and no relayer the commitment is returned unchanged, which
+/// keeps every v1 commitment revealable.
+pub fn bind_relayer(commitment: [u8; 32], relayer_fee: u64, relayer: &Pubkey) -> [u8; 32] {
+    if relayer_fee == 0 && *relayer == Pubkey::default() {
+        return commitment;
+    }
+    keccak::hashv(&[&commitment, &relayer_fee.to_le_bytes(), relayer.as_ref()]).0
+}
+
+/// A tree spend's double-spend tag, domain-separated from the commitment.
+/// On its own it does not identify the leaf, which is what a zk reveal
+/// would rely on; today's reveal publishes the preimage beside it anyway.
+pub fn compute_nullifier(secret: &[u8; 32], user_salt: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[b"cyrus-nullifier", secret, user_salt]).0
+}
+
+/// A tree leaf: the commitment plus the epoch it was deposited in, which
+/// `commit_to_tree` supplies, so `reveal_from_tree` can hold the same epoch
+/// separation as v1.
+pub fn tree_leaf(commitment: &[u8; 32], deposit_epoch: i64) -> [u8; 32] {
+    keccak::hashv(&[commitment, &deposit_epoch.to_le_bytes()]).0
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[left, right]).0
+}
+
+/// `zeros[i]` is the root of an empty subtree of height `i`.
+pub fn zero_hashes() -> [[u8; 32]; TREE_DEPTH + 1] {
+    let mut zeros = [[0u8; 32]; TREE_DEPTH + 1];
+    for i in 0..TREE_DEPTH {
+        zeros[i + 1] = hash_pair(&zeros[i], &zeros[i]);
+    }
+    zeros
+}
+
+/// The root `leaf` hashes up to along `proof`.
+pub fn merkle_root(leaf: [u8; 32], proof: &MerkleProof) -> Result<[u8; 32]> {
+    require!(proof.siblings.len() == TREE_DEPTH, TresorError::InvalidMerkleProof);
+    require!((proof.leaf_index as u64) < 1 << TREE_DEPTH, TresorError::InvalidMerkleProof);
+    let mut node = leaf;
+    for (level, sibling) in proof.siblings.iter().enumerate() {
+        node = if proof.leaf_index >> level & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+    }
+    Ok(node)
+}
+
+/// A leaf's position and its sibling at each level, leaf upwards, plus the
+/// deposit epoch bound into the leaf (see `tree_leaf`).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub leaf_index: u32,
+    pub deposit_epoch: i64,
+    pub siblings: Vec<[u8; 32]>,
+}
+
 // ---------------------------------------------------------------- state
 
 #[account]
 #[derive(InitSpace)]
 pub struct Config {
     pub authority: Pubkey,
+    /// Set by `propose_authority`, cleared by `accept_authority`.
+    pub pending_authority: Pubkey,
     pub fee_collector: Pubkey,
     pub fee_lamports: u64,
+    /// Per-depositor commit limiter: `rate_quota` commits per window.
+    pub rate_window_secs: i64,
+    pub rate_quota: u32,
+    /// Most commits a single bucket accepts per epoch; 0 = uncapped.
+    pub cohort_cap: u32,
+    /// `PAUSE_*` bitmask; a set bit halts that instruction class.
+    pub paused: u8,
     pub bump: u8,
 }
 
@@ -221,20 +771,155 @@ pub struct Pool {
     pub mint: Pubkey,
     #[max_len(MAX_BUCKETS)]
     pub sizes: Vec<u64>,
+    /// Epoch `cohort_counts` refers to; older counts are stale.
+    pub cohort_epoch: i64,
+    /// Commits per bucket in `cohort_epoch`, for `Config.cohort_cap`.
+    pub cohort_counts: [u32; MAX_BUCKETS],
+    /// Committed minus revealed, in the mint's base units: what custody owes.
+    pub outstanding: u64,
+    /// Retired buckets take no new commits. See `retire_bucket`.
+    pub retired: [bool; MAX_BUCKETS],
     pub bump: u8,
 }
 
+impl Pool {
+    /// Validates `sizes` as the pool's new schedule: non-empty, bounded,
+    /// non-zero, and extending the current one without changing it.
+    pub fn extend_sizes(&mut self, sizes: Vec<u64>) -> Result<()> {
+        require!(!sizes.is_empty(), TresorError::EmptyBucketSchedule);
+        require!(sizes.len() <= MAX_BUCKETS, TresorError::TooManyBuckets);
+        require!(sizes.iter().all(|s| *s > 0), TresorError::ZeroBucketSize);
+        require!(sizes.starts_with(&self.sizes), TresorError::BucketScheduleAppendOnly);
+        self.sizes = sizes;
+        Ok(())
+    }
+
+    /// The size of a bucket that still accepts commits.
+    pub fn open_bucket(&self, bucket_idx: u8) -> Result<u64> {
+        let size = *self.sizes.get(bucket_idx as usize).ok_or(TresorError::BadBucketIndex)?;
+        require!(!self.retired[bucket_idx as usize], TresorError::BucketRetired);
+        Ok(size)
+    }
+
+    /// Counts one commit into `bucket_idx`'s cohort for `epoch`, refusing it
+    /// once `cap` (0 = uncapped) is reached. Bounds how large a cohort one
+    /// funded spammer can fake within an epoch.
+    pub fn count_commit(&mut self, epoch: i64, bucket_idx: u8, cap: u32) -> Result<()> {
+        if self.cohort_epoch != epoch {
+            self.cohort_epoch = epoch;
+            self.cohort_counts = [0; MAX_BUCKETS];
+        }
+        let count = &mut self.cohort_counts[bucket_idx as usize];
+        require!(cap == 0 || *count < cap, TresorError::CohortFull);
+        *count += 1;
+        Ok(())
+    }
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct Commitment {
     pub hash: [u8; 32],
     pub mint: Pubkey,
     pub deposit_epoch: i64,
+    /// The bucket size at commit time; exactly this is paid on reveal.
+    pub amount: u64,
     pub bucket_idx: u8,
     pub spent: bool,
     pub bump: u8,
 }
 
+/// Per-depositor commit counter, seeds `[b"stats", depositor]`.
+#[account]
+#[derive(InitSpace)]
+pub struct UserStats {
+    /// Start of the window `count` belongs to, in unix seconds.
+    pub window: i64,
+    pub count: u32,
+    pub bump: u8,
+}
+
+/// One pool's custody, seeds `[b"pool_vault", mint]`, created by the first
+/// `set_buckets` for that mint. A SOL pool's deposits are this account's
+/// lamports above its rent-exempt minimum; a token pool's are in the ATA it
+/// owns.
+#[account]
+#[derive(InitSpace)]
+pub struct PoolVault {
+    pub bump: u8,
+}
+
+/// Tree-mode storage for one bucket, seeds `[b"tree", mint, [bucket_idx]]`: an
+/// append-only incremental Merkle tree keeping only the rightmost filled node
+/// per level, plus a ring of recent roots.
+#[account]
+#[derive(InitSpace)]
+pub struct CommitmentTree {
+    pub mint: Pubkey,
+    pub bucket_idx: u8,
+    /// The bucket size at `init_tree`; every leaf pays exactly this.
+    pub amount: u64,
+    pub next_index: u32,
+    pub filled_subtrees: [[u8; 32]; TREE_DEPTH],
+    pub roots: [[u8; 32]; ROOT_HISTORY_SIZE],
+    /// Slot in `roots` holding the current root.
+    pub current_root_index: u32,
+    pub bump: u8,
+}
+
+impl CommitmentTree {
+    /// Empties the tree: every level holds the zero subtree.
+    pub fn reset(&mut self) {
+        let zeros = zero_hashes();
+        self.filled_subtrees.copy_from_slice(&zeros[..TREE_DEPTH]);
+        self.roots = [[0; 32]; ROOT_HISTORY_SIZE];
+        self.roots[0] = zeros[TREE_DEPTH];
+        self.current_root_index = 0;
+        self.next_index = 0;
+    }
+
+    /// Appends `leaf`, pushes the new root into the history ring, and returns
+    /// the leaf's index.
+    pub fn insert(&mut self, leaf: [u8; 32]) -> Result<u32> {
+        let index = self.next_index;
+        require!((index as u64) < 1 << TREE_DEPTH, TresorError::TreeFull);
+        let zeros = zero_hashes();
+        let mut node = leaf;
+        for (level, zero) in zeros.iter().take(TREE_DEPTH).enumerate() {
+            node = if index >> level & 1 == 0 {
+                self.filled_subtrees[level] = node;
+                hash_pair(&node, zero)
+            } else {
+                hash_pair(&self.filled_subtrees[level], &node)
+            };
+        }
+        self.current_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE as u32;
+        self.roots[self.current_root_index as usize] = node;
+        self.next_index = index + 1;
+        Ok(index)
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.roots[self.current_root_index as usize]
+    }
+
+    /// True for the current root and the `ROOT_HISTORY_SIZE - 1` before it.
+    /// The all-zero filler of an unused slot never counts.
+    pub fn is_known_root(&self, root: &[u8; 32]) -> bool {
+        *root != [0; 32] && self.roots.contains(root)
+    }
+}
+
+/// A spent tree leaf, seeds `[b"nullifier", tree, nullifier_hash]`. Existence
+/// is the spent flag; the leaf it spent is in the matching `TreeReveal`.
+#[account]
+#[derive(InitSpace)]
+pub struct Nullifier {
+    pub bump: u8,
+}
+
+/// Accrued commit fees. The lamports live in this account, apart from every
+/// pool's custody.
 #[account]
 #[derive(InitSpace)]
 pub struct FeePot {
@@ -255,6 +940,22 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(address = config.authority @ TresorError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(address = config.pending_authority @ TresorError::Unauthorized)]
+    pub pending_authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
 #[derive(Accounts)]
 #[instruction(mint: Pubkey)]
 pub struct SetBuckets<'info> {
@@ -267,9 +968,24 @@ pub struct SetBuckets<'info> {
         seeds = [b"pool", mint.as_ref()], bump
     )]
     pub pool: Account<'info, Pool>,
+    #[account(
+        init_if_needed, payer = authority, space = 8 + PoolVault::INIT_SPACE,
+        seeds = [b"pool_vault", mint.as_ref()], bump
+    )]
+    pub pool_vault: Account<'info, PoolVault>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct RetireBucket<'info> {
+    #[account(address = config.authority @ TresorError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [b"pool", pool.mint.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+}
+
 #[derive(Accounts)]
 #[instruction(commitment: [u8; 32])]
 pub struct CommitToPool<'info> {
@@ -277,19 +993,67 @@ pub struct CommitToPool<'info> {
     pub depositor: Signer<'info>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
-    #[account(seeds = [b"pool", pool.mint.as_ref()], bump = pool.bump)]
+    #[account(
+        mut,
+        constraint = pool.mint == Pubkey::default() @ TresorError::PoolMintMismatch,
+        seeds = [b"pool", pool.mint.as_ref()], bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init_if_needed, payer = depositor, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", depositor.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    /// Freshness is enforced by `init`: a duplicate commitment cannot be created.
+    #[account(
+        init, payer = depositor, space = 8 + Commitment::INIT_SPACE,
+        seeds = [b"commit", commitment.as_ref()], bump
+    )]
+    pub commitment_account: Account<'info, Commitment>,
+    #[account(mut, seeds = [b"pool_vault", pool.mint.as_ref()], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, PoolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(commitment: [u8; 32])]
+pub struct CommitToPoolToken<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [b"pool", mint.key().as_ref()], bump = pool.bump)]
     pub pool: Account<'info, Pool>,
+    #[account(
+        init_if_needed, payer = depositor, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", depositor.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
     /// Freshness is enforced by `init`: a duplicate commitment cannot be created.
     #[account(
         init, payer = depositor, space = 8 + Commitment::INIT_SPACE,
         seeds = [b"commit", commitment.as_ref()], bump
     )]
     pub commitment_account: Account<'info, Commitment>,
-    /// CHECK: SOL-custody PDA for pooled deposits; balance-only.
-    #[account(mut, seeds = [b"pool_vault"], bump)]
-    pub pool_vault: UncheckedAccount<'info>,
+    #[account(mut, constraint = depositor_token_account.mint == mint.key() @ TresorError::PoolMintMismatch)]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// Owns the pool's token custody.
+    #[account(seeds = [b"pool_vault", mint.key().as_ref()], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, PoolVault>,
+    #[account(
+        init_if_needed, payer = depositor,
+        associated_token::mint = mint,
+        associated_token::authority = pool_vault,
+        associated_token::token_program = token_program,
+    )]
+    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
@@ -299,10 +1063,15 @@ pub struct RevealFromPool<'info> {
     pub caller: Signer<'info>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
-    #[account(seeds = [b"pool", pool.mint.as_ref()], bump = pool.bump)]
+    #[account(
+        mut,
+        constraint = pool.mint == Pubkey::default() @ TresorError::PoolMintMismatch,
+        seeds = [b"pool", pool.mint.as_ref()], bump = pool.bump
+    )]
     pub pool: Account<'info, Pool>,
     #[account(
         mut,
+        constraint = commitment_account.mint == pool.mint @ TresorError::PoolMintMismatch,
         seeds = [b"commit", commitment_account.hash.as_ref()],
         bump = commitment_account.bump
     )]
@@ -311,9 +1080,121 @@ pub struct RevealFromPool<'info> {
     /// changes the hash and the check fails.
     #[account(mut)]
     pub withdraw_to: UncheckedAccount<'info>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"pool_vault"], bump)]
-    pub pool_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"pool_vault", pool.mint.as_ref()], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, PoolVault>,
+}
+
+#[derive(Accounts)]
+pub struct RevealFromPoolToken<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [b"pool", mint.key().as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        constraint = commitment_account.mint == mint.key() @ TresorError::PoolMintMismatch,
+        seeds = [b"commit", commitment_account.hash.as_ref()],
+        bump = commitment_account.bump
+    )]
+    pub commitment_account: Account<'info, Commitment>,
+    /// CHECK: bearer payout identity, bound into the commitment hash; the
+    /// tokens go to `withdraw_to_token_account`, which it must own.
+    pub withdraw_to: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = withdraw_to_token_account.owner == withdraw_to.key() @ TresorError::Unauthorized,
+        constraint = withdraw_to_token_account.mint == mint.key() @ TresorError::PoolMintMismatch,
+    )]
+    pub withdraw_to_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// Receives the relayer fee; required only when `relayer_fee > 0`.
+    #[account(
+        mut,
+        constraint = caller_token_account.owner == caller.key() @ TresorError::Unauthorized,
+        constraint = caller_token_account.mint == mint.key() @ TresorError::PoolMintMismatch,
+    )]
+    pub caller_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Signs transfers out of the pool's token custody.
+    #[account(seeds = [b"pool_vault", mint.key().as_ref()], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, PoolVault>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = pool_vault,
+        associated_token::token_program = token_program,
+    )]
+    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(bucket_idx: u8)]
+pub struct InitTree<'info> {
+    #[account(mut, address = config.authority @ TresorError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        constraint = pool.mint == Pubkey::default() @ TresorError::PoolMintMismatch,
+        seeds = [b"pool", pool.mint.as_ref()], bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init, payer = authority, space = 8 + CommitmentTree::INIT_SPACE,
+        seeds = [b"tree", pool.mint.as_ref(), &[bucket_idx]], bump
+    )]
+    pub tree: Box<Account<'info, CommitmentTree>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitToTree<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [b"pool", tree.mint.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, seeds = [b"tree", tree.mint.as_ref(), &[tree.bucket_idx]], bump = tree.bump)]
+    pub tree: Box<Account<'info, CommitmentTree>>,
+    #[account(
+        init_if_needed, payer = depositor, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", depositor.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"pool_vault", tree.mint.as_ref()], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, PoolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(secret: [u8; 32], user_salt: [u8; 32], relayer_fee: u64, nullifier_hash: [u8; 32])]
+pub struct RevealFromTree<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [b"pool", tree.mint.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(seeds = [b"tree", tree.mint.as_ref(), &[tree.bucket_idx]], bump = tree.bump)]
+    pub tree: Box<Account<'info, CommitmentTree>>,
+    /// Freshness is enforced by `init`: a nullifier cannot be spent twice.
+    #[account(
+        init, payer = caller, space = 8 + Nullifier::INIT_SPACE,
+        seeds = [b"nullifier", tree.key().as_ref(), nullifier_hash.as_ref()], bump
+    )]
+    pub nullifier: Account<'info, Nullifier>,
+    /// CHECK: bearer payout target, bound into the leaf — tampering changes
+    /// the leaf and the proof fails.
+    #[account(mut)]
+    pub withdraw_to: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"pool_vault", tree.mint.as_ref()], bump = pool_vault.bump)]
+    pub pool_vault: Account<'info, PoolVault>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -325,9 +1206,6 @@ pub struct CollectFees<'info> {
     /// CHECK: enforced to equal the configured collector.
     #[account(mut, address = config.fee_collector @ TresorError::Unauthorized)]
     pub fee_collector: UncheckedAccount<'info>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"pool_vault"], bump)]
-    pub pool_vault: UncheckedAccount<'info>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
 }
@@ -348,6 +1226,73 @@ pub struct PoolReveal {
     pub mint: Pubkey,
     pub bucket_idx: u8,
     pub withdraw_to: Pubkey,
+    pub relayer_fee: u64,
+}
+
+#[event]
+pub struct TreeInitialized {
+    pub mint: Pubkey,
+    pub bucket_idx: u8,
+    pub amount: u64,
+    pub root: [u8; 32],
+}
+
+#[event]
+pub struct TreeDeposit {
+    pub commitment: [u8; 32],
+    pub mint: Pubkey,
+    pub bucket_idx: u8,
+    pub deposit_epoch: i64,
+    pub leaf_index: u32,
+    pub root: [u8; 32],
+}
+
+#[event]
+pub struct TreeReveal {
+    pub nullifier_hash: [u8; 32],
+    pub mint: Pubkey,
+    pub bucket_idx: u8,
+    /// The spent leaf and its epoch; both are public in the instruction data.
+    pub leaf_index: u32,
+    pub deposit_epoch: i64,
+    pub withdraw_to: Pubkey,
+    pub relayer_fee: u64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct FeeCollectorUpdated {
+    pub previous: Pubkey,
+    pub fee_collector: Pubkey,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub paused: u8,
+}
+
+#[event]
+pub struct BucketRetired {
+    pub mint: Pubkey,
+    pub bucket_idx: u8,
+}
+
+#[event]
+pub struct RateLimitsUpdated {
+    pub window_secs: i64,
+    pub quota: u32,
+    pub cohort_cap: u32,
 }
 
 #[error_code]
@@ -376,6 +1321,38 @@ pub enum TresorError {
     Unauthorized,
     #[msg("Nothing to collect")]
     NothingToCollect,
+    #[msg("Custody cannot cover this without breaching its rent floor")]
+    CustodyShortfall,
+    #[msg("This instruction class is paused")]
+    Paused,
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
+    #[msg("Rate limit exceeded")]
+    RateLimitExceeded,
+    #[msg("Rate-limit window and quota must be positive")]
+    InvalidRateLimit,
+    #[msg("Bucket has reached its commit cap for this epoch")]
+    CohortFull,
+    #[msg("Pool, commitment or token account is for a different mint")]
+    PoolMintMismatch,
+    #[msg("Mint extensions are incompatible with fixed-denomination pools")]
+    UnsupportedMint,
+    #[msg("Bucket schedules are append-only")]
+    BucketScheduleAppendOnly,
+    #[msg("Bucket is retired")]
+    BucketRetired,
+    #[msg("Relayer fee must be less than the committed amount")]
+    RelayerFeeTooHigh,
+    #[msg("Relayer fee needs a caller token account")]
+    RelayerAccountMissing,
+    #[msg("Commitment tree is full")]
+    TreeFull,
+    #[msg("Root is not in the tree's recent history")]
+    UnknownRoot,
+    #[msg("Merkle proof does not reach the supplied root")]
+    InvalidMerkleProof,
+    #[msg("Nullifier hash does not match the supplied preimage")]
+    NullifierMismatch,
 }
 
 // ---------------------------------------------------------------- tests
@@ -413,6 +1390,155 @@ mod tests {
         assert_ne!(base, compute_commitment(&[1; 32], &[2; 32], &pk(3), &pk(4), 0, &pk(9)));
     }
 
+    #[test]
+    fn relayer_binding_keeps_v1_and_pins_fee_and_relayer() {
+        let base = compute_commitment(&[1; 32], &[2; 32], &pk(3), &pk(4), 0, &pk(5));
+        // No fee, any relayer: the v1 commitment, untouched.
+        assert_eq!(bind_relayer(base, 0, &Pubkey::default()), base);
+
+        let relayed = bind_relayer(base, 1_000, &pk(7));
+        assert_ne!(relayed, base);
+        // Inflating the fee or swapping in another relayer breaks the match.
+        assert_ne!(relayed, bind_relayer(base, 1_001, &pk(7)));
+        assert_ne!(relayed, bind_relayer(base, 1_000, &pk(8)));
+        assert_ne!(relayed, bind_relayer(base, 1_000, &Pubkey::default()));
+    }
+
+    #[test]
+    fn cohort_cap_is_per_bucket_and_resets_each_epoch() {
+        let mut pool = Pool {
+            mint: pk(0),
+            sizes: vec![1, 2],
+            cohort_epoch: 0,
+            cohort_counts: [0; MAX_BUCKETS],
+            outstanding: 0,
+            retired: [false; MAX_BUCKETS],
+            bump: 0,
+        };
+        pool.count_commit(7, 0, 2).unwrap();
+        pool.count_commit(7, 0, 2).unwrap();
+        assert_eq!(pool.count_commit(7, 0, 2).unwrap_err(), TresorError::CohortFull.into());
+        pool.count_commit(7, 1, 2).unwrap();
+        pool.count_commit(8, 0, 2).unwrap();
+        assert_eq!(pool.cohort_counts[..2], [1, 0]);
+    }
+
+    #[test]
+    fn bucket_schedules_only_grow_and_retire() {
+        let mut pool = Pool {
+            mint: pk(0),
+            sizes: vec![],
+            cohort_epoch: 0,
+            cohort_counts: [0; MAX_BUCKETS],
+            outstanding: 0,
+            retired: [false; MAX_BUCKETS],
+            bump: 0,
+        };
+        pool.extend_sizes(vec![10, 20]).unwrap();
+        pool.extend_sizes(vec![10, 20, 50]).unwrap();
+        let rewrite = pool.extend_sizes(vec![10, 99, 50]);
+        assert_eq!(rewrite.unwrap_err(), TresorError::BucketScheduleAppendOnly.into());
+        assert!(pool.extend_sizes(vec![10]).is_err());
+        pool.retired[1] = true;
+        assert_eq!(pool.open_bucket(0).unwrap(), 10);
+        assert_eq!(pool.open_bucket(1).unwrap_err(), TresorError::BucketRetired.into());
+        assert_eq!(pool.open_bucket(3).unwrap_err(), TresorError::BadBucketIndex.into());
+    }
+
+    fn empty_tree() -> CommitmentTree {
+        let mut tree = CommitmentTree {
+            mint: pk(0),
+            bucket_idx: 0,
+            amount: 1,
+            next_index: 0,
+            filled_subtrees: [[0; 32]; TREE_DEPTH],
+            roots: [[0; 32]; ROOT_HISTORY_SIZE],
+            current_root_index: 0,
+            bump: 0,
+        };
+        tree.reset();
+        tree
+    }
+
+    /// Rebuilds the whole tree the slow way: root and path for `index`.
+    fn naive_proof(leaves: &[[u8; 32]], index: usize) -> ([u8; 32], MerkleProof) {
+        let zeros = zero_hashes();
+        let mut layer = leaves.to_vec();
+        let mut siblings = Vec::new();
+        for (level, zero) in zeros.iter().take(TREE_DEPTH).enumerate() {
+            siblings.push(*layer.get((index >> level) ^ 1).unwrap_or(zero));
+            layer = layer.chunks(2).map(|p| hash_pair(&p[0], p.get(1).unwrap_or(zero))).collect();
+        }
+        (layer[0], MerkleProof { leaf_index: index as u32, deposit_epoch: 0, siblings })
+    }
+
+    #[test]
+    fn tree_roots_and_paths_match_a_full_rebuild() {
+        let mut tree = empty_tree();
+        assert_eq!(tree.root(), zero_hashes()[TREE_DEPTH]);
+
+        let leaves: Vec<[u8; 32]> = (1..=5).map(|b| [b; 32]).collect();
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(tree.insert(*leaf).unwrap(), i as u32);
+            let (root, _) = naive_proof(&leaves[..=i], i);
+            assert_eq!(tree.root(), root);
+        }
+        for (i, leaf) in leaves.iter().enumerate() {
+            let (root, proof) = naive_proof(&leaves, i);
+            assert_eq!(merkle_root(*leaf, &proof).unwrap(), root);
+            // Right path, wrong leaf.
+            assert_ne!(merkle_root([9; 32], &proof).unwrap(), root);
+        }
+
+        let (_, mut short) = naive_proof(&leaves, 0);
+        short.siblings.pop();
+        assert_eq!(merkle_root(leaves[0], &short).unwrap_err(), TresorError::InvalidMerkleProof.into());
+    }
+
+    #[test]
+    fn leaves_bind_the_deposit_epoch() {
+        let c = compute_commitment(&[1; 32], &[2; 32], &pk(3), &pk(4), 0, &pk(5));
+        assert_eq!(tree_leaf(&c, 7), tree_leaf(&c, 7));
+        // Claiming an older epoch to dodge the same-epoch check changes the leaf.
+        assert_ne!(tree_leaf(&c, 7), tree_leaf(&c, 6));
+        assert_ne!(tree_leaf(&c, 7), c);
+    }
+
+    #[test]
+    fn root_history_is_a_bounded_ring() {
+        let mut tree = empty_tree();
+        let first = tree.root();
+        assert!(tree.is_known_root(&first));
+        assert!(!tree.is_known_root(&[0; 32]));
+
+        tree.insert([1; 32]).unwrap();
+        let after_one = tree.root();
+        for b in 2..ROOT_HISTORY_SIZE as u8 {
+            tree.insert([b; 32]).unwrap();
+        }
+        // ROOT_HISTORY_SIZE - 1 inserts later the empty root is still held...
+        assert!(tree.is_known_root(&first));
+        tree.insert([0xff; 32]).unwrap();
+        // ...and one more evicts it, but not the next.
+        assert!(!tree.is_known_root(&first));
+        assert!(tree.is_known_root(&after_one));
+    }
+
+    #[test]
+    fn full_tree_refuses_inserts() {
+        let mut tree = empty_tree();
+        tree.next_index = 1 << TREE_DEPTH;
+        assert_eq!(tree.insert([1; 32]).unwrap_err(), TresorError::TreeFull.into());
+    }
+
+    #[test]
+    fn nullifier_does_not_depend_on_the_payout_or_look_like_a_leaf() {
+        let n = compute_nullifier(&[1; 32], &[2; 32]);
+        assert_eq!(n, compute_nullifier(&[1; 32], &[2; 32]));
+        assert_ne!(n, compute_nullifier(&[1; 32], &[3; 32]));
+        assert_ne!(n, compute_commitment(&[1; 32], &[2; 32], &pk(3), &pk(4), 0, &pk(5)));
+    }
+
     #[test]
     fn epoch_length_matches_the_evm_contract() {
         assert_eq!(EPOCH_LENGTH, 3600);
@@ -9,14 +9,17 @@
 //! | `msg.value` on a payable fn           | explicit `amount` arg + system-program CPI    |
 //! | ERC-20 `transferFrom`                 | SPL Token CPI with an associated token account|
 //! | `nonReentrant`                        | not needed — Solana locks accounts per tx     |
-//! | Chainlink `latestRoundData()`         | `fee_lamports` in `Config` (see FEE below)    |
+//! | Chainlink `latestRoundData()`         | Pyth `PriceUpdateV2` or `fee_lamports`        |
 //!
 //! FEE. The EVM contract derives a $0.10 fee from a Chainlink feed at call time.
-//! Solana has no Chainlink; the equivalents are Pyth or Switchboard. Rather than
-//! bake in an oracle dependency that cannot be built or tested here, `Config`
-//! carries `fee_lamports`, set at `initialize` and updatable by the authority.
-//! `charge_fee` is the single choke point — swapping in a Pyth read means
-//! changing that one function and nothing else.
+//! Solana has no Chainlink; the equivalent here is Pyth. `Config` carries a
+//! fixed `fee_lamports`, set at `initialize` and updatable by the authority,
+//! and optionally a Pyth SOL/USD account plus a USD target (`set_price_feed`).
+//! `current_fee` is the single computation path: with a feed configured it
+//! prices the USD target off Pyth, and falls back to `fee_lamports` when the
+//! price is stale or its confidence band too wide. `charge_fee` is the single
+//! place a fee moves. The Pyth account is parsed by hand (`PythPrice`) so the
+//! whole path runs under `cargo test` against a synthetic fixture.
 //!
 //! DELIBERATE DIFFERENCE FROM THE EVM ORIGINAL: the EVM version tracks a
 //! per-user token list capped at `MAX_TOKENS_PER_USER = 200` purely so
@@ -27,17 +30,84 @@
 
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
+use anchor_spl::associated_token::{
+    self, get_associated_token_address_with_program_id, AssociatedToken,
+};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_hook, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    onchain::invoke_transfer_checked,
+};
 use anchor_spl::token_interface::{
     self, Mint, TokenAccount, TokenInterface, TransferChecked,
 };
 
 declare_id!("BGgf2b4L3q2Ekei7QxMEw2WNdzU7ffeARqoS7UaeKRuf");
 
-/// Mirrors `MAX_TRANSACTIONS_PER_MINUTE` / `RATE_LIMIT_WINDOW_MINUTE`.
-pub const MAX_TRANSACTIONS_PER_MINUTE: u16 = 1000;
+/// Mirrors `MAX_TRANSACTIONS_PER_MINUTE` / `RATE_LIMIT_WINDOW_MINUTE`; the
+/// defaults for `Config.rate_quota` / `Config.rate_window_secs`.
+pub const MAX_TRANSACTIONS_PER_MINUTE: u32 = 1000;
 pub const RATE_LIMIT_WINDOW_SECONDS: i64 = 60;
-/// Mirrors `MAX_BATCH_SIZE` for the batched instructions.
+/// Capacity of `Config.rate_exempt`.
+pub const MAX_RATE_EXEMPT: usize = 16;
+/// Longest withdrawal lock an owner can set (30 days), so a mistake cannot
+/// freeze a balance indefinitely.
+pub const MAX_TIMELOCK_DELAY: i64 = 30 * 24 * 60 * 60;
+/// Rolling window over which `VaultEntry.daily_cap` is measured.
+pub const OUTFLOW_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+/// Granularity of `VaultEntry.outflow`. A bucket counts in full while any
+/// part of it lies in the window, so the ring holds one more than the window
+/// spans.
+pub const OUTFLOW_BUCKET_SECONDS: i64 = 60 * 60;
+pub const OUTFLOW_BUCKETS: usize = (OUTFLOW_WINDOW_SECONDS / OUTFLOW_BUCKET_SECONDS) as usize + 1;
+/// How long a raised (or removed) daily cap waits before it applies.
+pub const DAILY_CAP_RAISE_DELAY: i64 = 24 * 60 * 60;
+/// Accounts one transaction may lock (the runtime's `MAX_TX_ACCOUNT_LOCKS`).
+/// Getting near it needs an address lookup table; a legacy message runs out
+/// of bytes at roughly 35 keys.
+pub const MAX_TX_ACCOUNT_LOCKS: usize = 64;
+/// What a batch has left for `remaining_accounts` once its named accounts
+/// (at most 10), the program id and a compute-budget instruction are counted.
+pub const MAX_BATCH_REMAINING_ACCOUNTS: usize = MAX_TX_ACCOUNT_LOCKS - 16;
+/// Mirrors `MAX_BATCH_SIZE` for the batched instructions. A batch must also
+/// fit in `MAX_BATCH_REMAINING_ACCOUNTS`, which binds first; size batches by
+/// the `MAX_*_BATCH_LEGS` constants below.
 pub const MAX_BATCH_SIZE: usize = 25;
+/// `remaining_accounts` per leg of `deposit_multiple_tokens`: mint, owner
+/// token account, vault token account, vault authority, `VaultEntry`,
+/// `MintPolicy` address, `MintTotals`.
+pub const DEPOSIT_BATCH_ACCOUNTS: usize = 7;
+/// `remaining_accounts` per SPL leg of `withdraw_multiple_tokens`: mint, owner
+/// token account, vault token account, vault authority, `VaultEntry`,
+/// `MintTotals`.
+pub const WITHDRAW_BATCH_TOKEN_ACCOUNTS: usize = 6;
+/// `remaining_accounts` per native SOL leg of `withdraw_multiple_tokens`:
+/// `VaultEntry`, `MintTotals`.
+pub const WITHDRAW_BATCH_SOL_ACCOUNTS: usize = 2;
+/// `remaining_accounts` per leg of `transfer_multiple_tokens_internal`:
+/// sender `VaultEntry`, recipient `VaultEntry`.
+pub const INTERNAL_BATCH_ACCOUNTS: usize = 2;
+/// Most legs that fit in one transaction, per batched instruction. A
+/// `withdraw_multiple_tokens` mixing SOL and SPL legs must keep its summed
+/// `remaining_accounts` within `MAX_BATCH_REMAINING_ACCOUNTS`.
+pub const MAX_DEPOSIT_BATCH_LEGS: usize = MAX_BATCH_REMAINING_ACCOUNTS / DEPOSIT_BATCH_ACCOUNTS;
+pub const MAX_WITHDRAW_TOKEN_BATCH_LEGS: usize = MAX_BATCH_REMAINING_ACCOUNTS / WITHDRAW_BATCH_TOKEN_ACCOUNTS;
+pub const MAX_WITHDRAW_SOL_BATCH_LEGS: usize = MAX_BATCH_REMAINING_ACCOUNTS / WITHDRAW_BATCH_SOL_ACCOUNTS;
+pub const MAX_INTERNAL_BATCH_LEGS: usize = MAX_BATCH_REMAINING_ACCOUNTS / INTERNAL_BATCH_ACCOUNTS;
+/// Mirrors `USD_FEE_CENTS = 10` ($0.10), the default oracle fee target.
+pub const USD_FEE_CENTS: u64 = 10;
+pub const BPS_DENOM: u64 = 10_000;
+/// Pyth Solana receiver; owns every `PriceUpdateV2` account.
+pub const PYTH_RECEIVER_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+/// `sha256("account:PriceUpdateV2")[..8]`.
+pub const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+/// `Config.paused` bits. Numbered consistently across the three programs
+/// (tresor uses bits 3-4, fundraise bit 5) so one runbook table covers all.
+pub const PAUSE_DEPOSITS: u8 = 1 << 0;
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 1;
+pub const PAUSE_INTERNAL_TRANSFERS: u8 = 1 << 2;
+pub const PAUSE_ALL: u8 = PAUSE_DEPOSITS | PAUSE_WITHDRAWALS | PAUSE_INTERNAL_TRANSFERS;
 
 #[program]
 pub mod cyrus_vault {
@@ -56,7 +126,131 @@ pub mod cyrus_vault {
         cfg.fee_collector = fee_collector;
         cfg.salt = salt;
         cfg.fee_lamports = fee_lamports;
+        cfg.open_mode = true;
+        cfg.rate_window_secs = RATE_LIMIT_WINDOW_SECONDS;
+        cfg.rate_quota = MAX_TRANSACTIONS_PER_MINUTE;
         cfg.bump = ctx.bumps.config;
+        ctx.accounts.global_stats.bump = ctx.bumps.global_stats;
+        ctx.accounts.fee_pot.bump = ctx.bumps.fee_pot;
+        ctx.accounts.sol_vault.bump = ctx.bumps.sol_vault;
+        Ok(())
+    }
+
+    /// Authority-only. First half of a two-step handover: nothing changes until
+    /// `new_authority` signs `accept_authority`, so a typo cannot brick the
+    /// program. `Pubkey::default()` withdraws a pending proposal.
+    pub fn propose_authority(ctx: Context<UpdateConfig>, new_authority: Pubkey) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        cfg.pending_authority = new_authority;
+        emit!(AuthorityProposed { authority: cfg.authority, pending_authority: new_authority });
+        Ok(())
+    }
+
+    /// Signed by the proposed authority; completes the handover.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        let previous = cfg.authority;
+        cfg.authority = cfg.pending_authority;
+        cfg.pending_authority = Pubkey::default();
+        emit!(AuthorityTransferred { previous, authority: cfg.authority });
+        Ok(())
+    }
+
+    /// Authority-only. Rotates where fees go; same zero-address rule as
+    /// `initialize`.
+    pub fn set_fee_collector(ctx: Context<UpdateConfig>, fee_collector: Pubkey) -> Result<()> {
+        require!(fee_collector != Pubkey::default(), VaultError::InvalidFeeCollector);
+        let cfg = &mut ctx.accounts.config;
+        let previous = cfg.fee_collector;
+        cfg.fee_collector = fee_collector;
+        emit!(FeeCollectorUpdated { previous, fee_collector });
+        Ok(())
+    }
+
+    /// Authority-only. Replaces the pause bitmask wholesale. Classes are
+    /// independent, so deposits can be stopped while withdrawals stay open.
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, VaultError::InvalidPauseFlags);
+        ctx.accounts.config.paused = paused;
+        emit!(PauseUpdated { paused });
+        Ok(())
+    }
+
+    /// Authority-only. Creates or updates the deposit policy for one mint.
+    /// `max_deposit` / `min_deposit` of 0 mean "no bound".
+    pub fn set_mint_policy(
+        ctx: Context<SetMintPolicy>,
+        mint: Pubkey,
+        allowed: bool,
+        max_deposit: u64,
+        min_deposit: u64,
+    ) -> Result<()> {
+        require!(
+            max_deposit == 0 || min_deposit <= max_deposit,
+            VaultError::InvalidMintPolicy
+        );
+        let policy = &mut ctx.accounts.mint_policy;
+        policy.mint = mint;
+        policy.allowed = allowed;
+        policy.max_deposit = max_deposit;
+        policy.min_deposit = min_deposit;
+        policy.bump = ctx.bumps.mint_policy;
+        emit!(MintPolicyUpdated { mint, allowed, max_deposit, min_deposit });
+        Ok(())
+    }
+
+    /// Authority-only. In open mode a mint without a `MintPolicy` is accepted,
+    /// as before the registry existed; closed, only listed mints are.
+    pub fn set_open_mode(ctx: Context<UpdateConfig>, open_mode: bool) -> Result<()> {
+        ctx.accounts.config.open_mode = open_mode;
+        emit!(OpenModeUpdated { open_mode });
+        Ok(())
+    }
+
+    /// Authority-only. Caps the total custodied for `mint` (`Pubkey::default()`
+    /// for native SOL); 0 lifts the cap. Lowering it below the current total
+    /// only stops new deposits — withdrawals are never blocked by it.
+    pub fn set_tvl_cap(ctx: Context<SetTvlCap>, mint: Pubkey, cap: u64) -> Result<()> {
+        let totals = &mut ctx.accounts.mint_totals;
+        totals.mint = mint;
+        totals.cap = cap;
+        totals.bump = ctx.bumps.mint_totals;
+        emit!(TvlCapUpdated { mint, cap, total: totals.total });
+        Ok(())
+    }
+
+    /// Authority-only. Retunes the limiter: window length, per-user quota, and
+    /// the all-users quota (0 disables the global limiter). Quotas are in
+    /// weight units — 1 per instruction, or the leg count of a batch.
+    pub fn set_rate_limits(
+        ctx: Context<UpdateConfig>,
+        window_secs: i64,
+        quota: u32,
+        global_quota: u32,
+    ) -> Result<()> {
+        require!(window_secs > 0 && quota > 0, VaultError::InvalidRateLimit);
+        let cfg = &mut ctx.accounts.config;
+        cfg.rate_window_secs = window_secs;
+        cfg.rate_quota = quota;
+        cfg.global_rate_quota = global_quota;
+        emit!(RateLimitsUpdated { window_secs, quota, global_quota });
+        Ok(())
+    }
+
+    /// Authority-only. Adds `wallet` to, or removes it from, the per-user
+    /// rate-limit exemption list. Exempt wallets still count towards the
+    /// global limiter.
+    pub fn set_rate_exemption(ctx: Context<UpdateConfig>, wallet: Pubkey, exempt: bool) -> Result<()> {
+        let list = &mut ctx.accounts.config.rate_exempt;
+        if exempt {
+            if !list.contains(&wallet) {
+                require!(list.len() < MAX_RATE_EXEMPT, VaultError::RateExemptListFull);
+                list.push(wallet);
+            }
+        } else {
+            list.retain(|w| *w != wallet);
+        }
+        emit!(RateExemptionUpdated { wallet, exempt });
         Ok(())
     }
 
@@ -66,12 +260,47 @@ pub mod cyrus_vault {
         Ok(())
     }
 
+    /// Authority-only. Switches to the oracle fee (`price_feed` set) or back to
+    /// the fixed fee (`price_feed == Pubkey::default()`). `fee_lamports` stays
+    /// in force as the fallback either way.
+    pub fn set_price_feed(
+        ctx: Context<UpdateConfig>,
+        price_feed: Pubkey,
+        fee_usd_cents: u64,
+        max_price_age: u64,
+        max_conf_bps: u16,
+    ) -> Result<()> {
+        if price_feed != Pubkey::default() {
+            require!(fee_usd_cents > 0, VaultError::InvalidOracleConfig);
+            require!(max_price_age > 0, VaultError::InvalidOracleConfig);
+            require!(
+                max_conf_bps > 0 && u64::from(max_conf_bps) <= BPS_DENOM,
+                VaultError::InvalidOracleConfig
+            );
+        }
+        let cfg = &mut ctx.accounts.config;
+        cfg.price_feed = price_feed;
+        cfg.fee_usd_cents = fee_usd_cents;
+        cfg.max_price_age = max_price_age;
+        cfg.max_conf_bps = max_conf_bps;
+        Ok(())
+    }
+
     /// `depositETH()`. `amount` is the GROSS amount, exactly like `msg.value`:
     /// the fee is taken out of it and the remainder is credited.
     pub fn deposit_sol(ctx: Context<DepositSol>, amount: u64) -> Result<()> {
-        let fee = ctx.accounts.config.fee_lamports;
+        require_active(&ctx.accounts.config, PAUSE_DEPOSITS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
         require!(amount > fee, VaultError::AmountBelowFee);
-        rate_limit(&mut ctx.accounts.user_stats, &Clock::get()?)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
 
         // Move the whole amount into the program's SOL vault, then split the
         // accounting: credited to the user, fee to the fee pot.
@@ -93,6 +322,11 @@ pub mod cyrus_vault {
         entry.amount = entry.amount.checked_add(credited).ok_or(VaultError::MathOverflow)?;
         entry.bump = ctx.bumps.vault_entry;
 
+        let totals = &mut ctx.accounts.mint_totals;
+        totals.mint = Pubkey::default();
+        totals.bump = ctx.bumps.mint_totals;
+        totals.credit(credited)?;
+
         let pot = &mut ctx.accounts.fee_pot;
         pot.accrued = pot.accrued.checked_add(fee).ok_or(VaultError::MathOverflow)?;
 
@@ -101,63 +335,90 @@ pub mod cyrus_vault {
     }
 
     /// `withdrawETH(amount)`. Fee is charged on top, from the caller's wallet.
-    pub fn withdraw_sol(ctx: Context<WithdrawSol>, amount: u64) -> Result<()> {
-        let fee = ctx.accounts.config.fee_lamports;
-        rate_limit(&mut ctx.accounts.user_stats, &Clock::get()?)?;
+    /// With `close_if_empty`, a fully drained entry is closed and its rent
+    /// returned, like the EVM `_removeTokenIfZero`.
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>, amount: u64, close_if_empty: bool) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
+        check_timelock(
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.vault_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
         require!(ctx.accounts.vault_entry.amount >= amount, VaultError::InsufficientBalance);
 
-        // Fee first, from the wallet (mirrors `_chargeFeeFromWallet`).
-        system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.owner.to_account_info(),
-                    to: ctx.accounts.sol_vault.to_account_info(),
-                },
-            ),
+        // Fee first, from the wallet.
+        charge_fee(
             fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
         )?;
-        let pot = &mut ctx.accounts.fee_pot;
-        pot.accrued = pot.accrued.checked_add(fee).ok_or(VaultError::MathOverflow)?;
 
         // Debit bookkeeping BEFORE moving lamports.
         let entry = &mut ctx.accounts.vault_entry;
-        entry.amount = entry.amount.checked_sub(amount).ok_or(VaultError::MathOverflow)?;
+        entry.debit(amount, clock.unix_timestamp)?;
+        ctx.accounts.mint_totals.debit(amount)?;
 
         // PDA -> owner. Direct lamport arithmetic: a PDA owned by this program
         // cannot use the system program's transfer CPI.
-        **ctx.accounts.sol_vault.to_account_info().try_borrow_mut_lamports()? -= amount;
-        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? += amount;
+        pay_from_sol_vault(&ctx.accounts.sol_vault, &ctx.accounts.owner.to_account_info(), amount)?;
 
         emit!(Withdraw { owner: entry.owner, mint: Pubkey::default(), amount, fee });
-        Ok(())
+        settle_entry(&mut ctx.accounts.vault_entry, close_if_empty, &ctx.accounts.owner)
     }
 
     /// `transferInternalETH(to, amount)` — ledger move, no lamports leave the program.
-    pub fn transfer_internal_sol(ctx: Context<TransferInternalSol>, amount: u64) -> Result<()> {
-        let fee = ctx.accounts.config.fee_lamports;
-        rate_limit(&mut ctx.accounts.user_stats, &Clock::get()?)?;
+    /// `close_if_empty` closes the sender's entry if this drains it.
+    pub fn transfer_internal_sol(
+        ctx: Context<TransferInternalSol>,
+        amount: u64,
+        close_if_empty: bool,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_INTERNAL_TRANSFERS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
+        check_timelock(
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.from_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
         require!(ctx.accounts.from_entry.amount >= amount, VaultError::InsufficientBalance);
         require!(
             ctx.accounts.from_entry.key() != ctx.accounts.to_entry.key(),
             VaultError::SelfTransfer
         );
 
-        system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.owner.to_account_info(),
-                    to: ctx.accounts.sol_vault.to_account_info(),
-                },
-            ),
+        charge_fee(
             fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
         )?;
-        let pot = &mut ctx.accounts.fee_pot;
-        pot.accrued = pot.accrued.checked_add(fee).ok_or(VaultError::MathOverflow)?;
 
         let from = &mut ctx.accounts.from_entry;
-        from.amount = from.amount.checked_sub(amount).ok_or(VaultError::MathOverflow)?;
+        from.debit(amount, clock.unix_timestamp)?;
         let to = &mut ctx.accounts.to_entry;
         to.owner = ctx.accounts.recipient.key();
         to.mint = Pubkey::default();
@@ -170,326 +431,2729 @@ pub mod cyrus_vault {
             mint: Pubkey::default(),
             amount,
         });
-        Ok(())
+        settle_entry(&mut ctx.accounts.from_entry, close_if_empty, &ctx.accounts.owner)
+    }
+
+    /// `transferInternalToken(to, token, amount)` — the SPL counterpart of
+    /// `transfer_internal_sol`. Ledger move only; the tokens stay in custody.
+    pub fn transfer_internal_token(
+        ctx: Context<TransferInternalToken>,
+        amount: u64,
+        close_if_empty: bool,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_INTERNAL_TRANSFERS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
+        check_timelock(
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.from_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        require!(ctx.accounts.from_entry.amount >= amount, VaultError::InsufficientBalance);
+        require!(
+            ctx.accounts.from_entry.key() != ctx.accounts.to_entry.key(),
+            VaultError::SelfTransfer
+        );
+
+        charge_fee(
+            fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let mint_key = ctx.accounts.mint.key();
+        let from = &mut ctx.accounts.from_entry;
+        from.debit(amount, clock.unix_timestamp)?;
+        let to = &mut ctx.accounts.to_entry;
+        to.owner = ctx.accounts.recipient.key();
+        to.mint = mint_key;
+        to.amount = to.amount.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        to.bump = ctx.bumps.to_entry;
+
+        emit!(InternalTransfer {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.recipient.key(),
+            mint: mint_key,
+            amount,
+        });
+        settle_entry(&mut ctx.accounts.from_entry, close_if_empty, &ctx.accounts.owner)
     }
 
     /// `depositToken(token, amount)`. The SPL fee is still paid in SOL, matching
     /// the EVM contract where the $0.10 is always native.
-    pub fn deposit_token(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
+    ///
+    /// Token-2022: the ledger is credited with what custody actually received,
+    /// so a TransferFee mint credits `amount` minus the withheld fee. For a
+    /// transfer-hook mint, `remaining_accounts` carries the hook's extra
+    /// accounts (its `ExtraAccountMetaList` PDA, the accounts it lists, and
+    /// the hook program), exactly as a client would pass to `transferChecked`.
+    pub fn deposit_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositToken<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_DEPOSITS)?;
         require!(amount > 0, VaultError::ZeroAmount);
-        let fee = ctx.accounts.config.fee_lamports;
-        rate_limit(&mut ctx.accounts.user_stats, &Clock::get()?)?;
+        inspect_mint(&ctx.accounts.mint.to_account_info())?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
 
-        system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.owner.to_account_info(),
-                    to: ctx.accounts.sol_vault.to_account_info(),
-                },
-            ),
+        charge_fee(
             fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
         )?;
-        let pot = &mut ctx.accounts.fee_pot;
-        pot.accrued = pot.accrued.checked_add(fee).ok_or(VaultError::MathOverflow)?;
 
-        token_interface::transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.owner_token_account.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.vault_token_account.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info(),
-                },
-            ),
+        let before = ctx.accounts.vault_token_account.amount;
+        invoke_transfer_checked(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.owner_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.remaining_accounts,
             amount,
             ctx.accounts.mint.decimals,
+            &[],
         )?;
+        ctx.accounts.vault_token_account.reload()?;
+        let received = received_delta(before, ctx.accounts.vault_token_account.amount)?;
+        // Bounds apply to what is credited, net of any transfer fee.
+        check_mint_policy(&ctx.accounts.config, &ctx.accounts.mint_policy, received)?;
 
         let entry = &mut ctx.accounts.vault_entry;
         entry.owner = ctx.accounts.owner.key();
         entry.mint = ctx.accounts.mint.key();
-        entry.amount = entry.amount.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        entry.amount = entry.amount.checked_add(received).ok_or(VaultError::MathOverflow)?;
         entry.bump = ctx.bumps.vault_entry;
 
-        emit!(Deposit { owner: entry.owner, mint: entry.mint, amount, fee });
+        let totals = &mut ctx.accounts.mint_totals;
+        totals.mint = entry.mint;
+        totals.bump = ctx.bumps.mint_totals;
+        totals.credit(received)?;
+
+        emit!(Deposit { owner: entry.owner, mint: entry.mint, amount: received, fee });
         Ok(())
     }
 
-    /// `withdrawToken(token, amount)`.
-    pub fn withdraw_token(ctx: Context<WithdrawToken>, amount: u64) -> Result<()> {
-        let fee = ctx.accounts.config.fee_lamports;
-        rate_limit(&mut ctx.accounts.user_stats, &Clock::get()?)?;
+    /// `withdrawToken(token, amount)`. Custody always gives up exactly
+    /// `amount`; a TransferFee mint withholds its fee on the owner's side.
+    /// Transfer-hook extra accounts go in `remaining_accounts`, as for
+    /// `deposit_token`. `close_if_empty` as for `withdraw_sol`.
+    pub fn withdraw_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawToken<'info>>,
+        amount: u64,
+        close_if_empty: bool,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        inspect_mint(&ctx.accounts.mint.to_account_info())?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
+        check_timelock(
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.vault_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
         require!(ctx.accounts.vault_entry.amount >= amount, VaultError::InsufficientBalance);
 
-        system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.owner.to_account_info(),
-                    to: ctx.accounts.sol_vault.to_account_info(),
-                },
-            ),
+        charge_fee(
             fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
         )?;
-        let pot = &mut ctx.accounts.fee_pot;
-        pot.accrued = pot.accrued.checked_add(fee).ok_or(VaultError::MathOverflow)?;
 
         let entry = &mut ctx.accounts.vault_entry;
-        entry.amount = entry.amount.checked_sub(amount).ok_or(VaultError::MathOverflow)?;
+        entry.debit(amount, clock.unix_timestamp)?;
+        ctx.accounts.mint_totals.debit(amount)?;
 
         let mint_key = ctx.accounts.mint.key();
         let seeds: &[&[u8]] = &[b"token_vault", mint_key.as_ref(), &[ctx.bumps.vault_authority]];
-        token_interface::transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.vault_token_account.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.owner_token_account.to_account_info(),
-                    authority: ctx.accounts.vault_authority.to_account_info(),
-                },
-                &[seeds],
-            ),
+        invoke_transfer_checked(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.owner_token_account.to_account_info(),
+            ctx.accounts.vault_authority.to_account_info(),
+            ctx.remaining_accounts,
             amount,
             ctx.accounts.mint.decimals,
+            &[seeds],
         )?;
 
         emit!(Withdraw { owner: entry.owner, mint: mint_key, amount, fee });
+        settle_entry(&mut ctx.accounts.vault_entry, close_if_empty, &ctx.accounts.owner)
+    }
+
+    /// `depositMultipleTokens(tokens, amounts)`. One fee for the whole batch,
+    /// exactly like the EVM contract. `remaining_accounts` carries one group per
+    /// leg, in the order of `amounts`: `[mint, owner ATA, vault ATA,
+    /// vault_authority, VaultEntry, MintPolicy, MintTotals]`, the vault ATA and
+    /// `MintTotals` being created on first use and the `MintPolicy` address
+    /// passed whether or not the authority has created it.
+    /// Every group is validated before use; any bad leg fails the transaction.
+    /// All legs share the one `token_program`, so a batch is either all legacy
+    /// SPL or all Token-2022 mints. At most `MAX_DEPOSIT_BATCH_LEGS` legs.
+    pub fn deposit_multiple_tokens<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositMultipleTokens<'info>>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_DEPOSITS)?;
+        validate_batch(
+            &amounts,
+            ctx.remaining_accounts.len(),
+            amounts.len() * DEPOSIT_BATCH_ACCOUNTS,
+        )?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            amounts.len(),
+            &clock,
+        )?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let owner = ctx.accounts.owner.to_account_info();
+        let owner_key = owner.key();
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(amounts.len());
+        for (leg, &amount) in ctx.remaining_accounts.chunks(DEPOSIT_BATCH_ACCOUNTS).zip(amounts.iter()) {
+            let mint = InterfaceAccount::<Mint>::try_from(&leg[0])?;
+            let mint_key = mint.key();
+            // Hook accounts cannot share `remaining_accounts` with the legs.
+            require!(!inspect_mint(&leg[0])?, VaultError::TransferHookInBatch);
+            // Two legs on one mint would load the same VaultEntry twice and the
+            // second write would clobber the first credit.
+            require!(!seen.contains(&mint_key), VaultError::DuplicateMint);
+            seen.push(mint_key);
+            check_policy_address(&leg[5], &mint_key)?;
+
+            let owner_token_account = InterfaceAccount::<TokenAccount>::try_from(&leg[1])?;
+            require_keys_eq!(owner_token_account.mint, mint_key, VaultError::MintMismatch);
+            let vault_authority = &leg[3];
+            let (expected_authority, _) =
+                Pubkey::find_program_address(&[b"token_vault", mint_key.as_ref()], &crate::ID);
+            require_keys_eq!(
+                vault_authority.key(),
+                expected_authority,
+                VaultError::InvalidVaultAuthority
+            );
+            if leg[2].data_is_empty() {
+                associated_token::create_idempotent(CpiContext::new(
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    associated_token::Create {
+                        payer: owner.clone(),
+                        associated_token: leg[2].clone(),
+                        authority: vault_authority.clone(),
+                        mint: mint.to_account_info(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        token_program: ctx.accounts.token_program.to_account_info(),
+                    },
+                ))?;
+            }
+            let mut vault_token_account = custody_account(
+                &leg[2],
+                &expected_authority,
+                &mint_key,
+                &ctx.accounts.token_program.key(),
+            )?;
+            let before = vault_token_account.amount;
+            let mut entry = load_or_init_entry(
+                &leg[4],
+                &owner_key,
+                &mint_key,
+                &owner,
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+            let mut totals = load_or_init_totals(
+                &leg[6],
+                &mint_key,
+                &owner,
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: owner_token_account.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: vault_token_account.to_account_info(),
+                        authority: owner.clone(),
+                    },
+                ),
+                amount,
+                mint.decimals,
+            )?;
+            vault_token_account.reload()?;
+            let received = received_delta(before, vault_token_account.amount)?;
+            check_mint_policy(&ctx.accounts.config, &leg[5], received)?;
+
+            entry.amount = entry.amount.checked_add(received).ok_or(VaultError::MathOverflow)?;
+            entry.exit(&crate::ID)?;
+            totals.credit(received)?;
+            totals.exit(&crate::ID)?;
+
+            // The batch fee is reported once, on `BatchOperation`.
+            emit!(Deposit { owner: owner_key, mint: mint_key, amount: received, fee: 0 });
+        }
+
+        emit!(BatchOperation { owner: owner_key, count: amounts.len() as u8, fee });
         Ok(())
     }
 
-    /// `collectFees()` — sweeps the accrued fee pot to the configured collector.
-    pub fn collect_fees(ctx: Context<CollectFees>) -> Result<()> {
-        let amount = ctx.accounts.fee_pot.accrued;
-        require!(amount > 0, VaultError::NothingToCollect);
-        ctx.accounts.fee_pot.accrued = 0;
+    /// `withdrawMultipleTokens(tokens, amounts)`. One fee for the whole batch.
+    /// `mints[i]` selects the layout of leg `i` in `remaining_accounts`:
+    /// native SOL (`Pubkey::default()`) is `[VaultEntry, MintTotals]` and pays
+    /// out of `sol_vault`; an SPL mint is `[mint, owner ATA, vault ATA,
+    /// vault_authority, VaultEntry, MintTotals]` and is signed for by that mint's
+    /// `[b"token_vault", mint]` PDA. `close_if_empty` applies to every leg.
+    /// SPL legs share the one `token_program`, so they cannot mix legacy SPL
+    /// and Token-2022 mints. See `MAX_WITHDRAW_*_BATCH_LEGS` for sizing.
+    pub fn withdraw_multiple_tokens<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawMultipleTokens<'info>>,
+        mints: Vec<Pubkey>,
+        amounts: Vec<u64>,
+        close_if_empty: bool,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        require!(mints.len() == amounts.len(), VaultError::ArrayLengthMismatch);
+        let expected_accounts = mints
+            .iter()
+            .map(|m| {
+                if *m == Pubkey::default() {
+                    WITHDRAW_BATCH_SOL_ACCOUNTS
+                } else {
+                    WITHDRAW_BATCH_TOKEN_ACCOUNTS
+                }
+            })
+            .sum();
+        validate_batch(&amounts, ctx.remaining_accounts.len(), expected_accounts)?;
+        for (i, mint) in mints.iter().enumerate() {
+            require!(!mints[..i].contains(mint), VaultError::DuplicateMint);
+        }
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            amounts.len(),
+            &clock,
+        )?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
 
-        **ctx.accounts.sol_vault.to_account_info().try_borrow_mut_lamports()? -= amount;
-        **ctx.accounts.fee_collector.to_account_info().try_borrow_mut_lamports()? += amount;
+        let owner_key = ctx.accounts.owner.key();
+        let mut accounts = ctx.remaining_accounts.iter();
+        let mut next = || accounts.next().ok_or(VaultError::BatchAccountsMismatch);
+        for (mint_key, &amount) in mints.iter().zip(amounts.iter()) {
+            if *mint_key == Pubkey::default() {
+                let mut entry = load_entry(next()?, &owner_key, mint_key)?;
+                check_timelock(&mut ctx.accounts.user_stats, &mut entry, amount, clock.unix_timestamp)?;
+                require!(entry.amount >= amount, VaultError::InsufficientBalance);
+                entry.debit(amount, clock.unix_timestamp)?;
+                settle_entry(&mut entry, close_if_empty, &ctx.accounts.owner)?;
+                let mut totals = load_totals(next()?, mint_key)?;
+                totals.debit(amount)?;
+                totals.exit(&crate::ID)?;
 
-        emit!(FeesCollected { to: ctx.accounts.fee_collector.key(), amount });
+                pay_from_sol_vault(
+                    &ctx.accounts.sol_vault,
+                    &ctx.accounts.owner.to_account_info(),
+                    amount,
+                )?;
+            } else {
+                let mint = InterfaceAccount::<Mint>::try_from(next()?)?;
+                require_keys_eq!(mint.key(), *mint_key, VaultError::MintMismatch);
+                require!(
+                    !inspect_mint(&mint.to_account_info())?,
+                    VaultError::TransferHookInBatch
+                );
+                let owner_token_account = InterfaceAccount::<TokenAccount>::try_from(next()?)?;
+                require_keys_eq!(owner_token_account.mint, *mint_key, VaultError::MintMismatch);
+                let vault_token_info = next()?;
+                let vault_authority = next()?;
+                let (expected_authority, authority_bump) = Pubkey::find_program_address(
+                    &[b"token_vault", mint_key.as_ref()],
+                    &crate::ID,
+                );
+                require_keys_eq!(
+                    vault_authority.key(),
+                    expected_authority,
+                    VaultError::InvalidVaultAuthority
+                );
+                let vault_token_account = custody_account(
+                    vault_token_info,
+                    &expected_authority,
+                    mint_key,
+                    &ctx.accounts.token_program.key(),
+                )?;
+
+                let mut entry = load_entry(next()?, &owner_key, mint_key)?;
+                check_timelock(&mut ctx.accounts.user_stats, &mut entry, amount, clock.unix_timestamp)?;
+                require!(entry.amount >= amount, VaultError::InsufficientBalance);
+                entry.debit(amount, clock.unix_timestamp)?;
+                settle_entry(&mut entry, close_if_empty, &ctx.accounts.owner)?;
+                let mut totals = load_totals(next()?, mint_key)?;
+                totals.debit(amount)?;
+                totals.exit(&crate::ID)?;
+
+                let seeds: &[&[u8]] = &[b"token_vault", mint_key.as_ref(), &[authority_bump]];
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        TransferChecked {
+                            from: vault_token_account.to_account_info(),
+                            mint: mint.to_account_info(),
+                            to: owner_token_account.to_account_info(),
+                            authority: vault_authority.clone(),
+                        },
+                        &[seeds],
+                    ),
+                    amount,
+                    mint.decimals,
+                )?;
+            }
+
+            emit!(Withdraw { owner: owner_key, mint: *mint_key, amount, fee: 0 });
+        }
+
+        emit!(BatchOperation { owner: owner_key, count: amounts.len() as u8, fee });
         Ok(())
     }
-}
 
-/// Mirrors `_checkAndUpdateRateLimit`.
-fn rate_limit(stats: &mut Account<UserStats>, clock: &Clock) -> Result<()> {
-    let window = clock.unix_timestamp / RATE_LIMIT_WINDOW_SECONDS;
-    if stats.window == window {
+    /// `transferMultipleTokensInternal(tokens, to, amounts)`. Ledger-only: moves
+    /// several balances to one recipient for a single fee. `remaining_accounts`
+    /// carries `[from VaultEntry, to VaultEntry]` per leg, in the order of
+    /// `mints`; recipient entries are created on demand. `Pubkey::default()`
+    /// is native SOL, as everywhere else in the ledger. `close_if_empty`
+    /// applies to every sender entry. At most `MAX_INTERNAL_BATCH_LEGS` legs.
+    pub fn transfer_multiple_tokens_internal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, TransferMultipleTokensInternal<'info>>,
+        mints: Vec<Pubkey>,
+        amounts: Vec<u64>,
+        close_if_empty: bool,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_INTERNAL_TRANSFERS)?;
+        require!(mints.len() == amounts.len(), VaultError::ArrayLengthMismatch);
+        validate_batch(
+            &amounts,
+            ctx.remaining_accounts.len(),
+            amounts.len() * INTERNAL_BATCH_ACCOUNTS,
+        )?;
+        for (i, mint) in mints.iter().enumerate() {
+            require!(!mints[..i].contains(mint), VaultError::DuplicateMint);
+        }
+        let owner_key = ctx.accounts.owner.key();
+        let recipient_key = ctx.accounts.recipient.key();
+        require!(recipient_key != Pubkey::default(), VaultError::InvalidRecipient);
+        require!(recipient_key != owner_key, VaultError::SelfTransfer);
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            amounts.len(),
+            &clock,
+        )?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let owner = ctx.accounts.owner.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        for ((leg, mint), &amount) in ctx
+            .remaining_accounts
+            .chunks(INTERNAL_BATCH_ACCOUNTS)
+            .zip(mints.iter())
+            .zip(amounts.iter())
+        {
+            let mut from = load_entry(&leg[0], &owner_key, mint)?;
+            check_timelock(&mut ctx.accounts.user_stats, &mut from, amount, clock.unix_timestamp)?;
+            require!(from.amount >= amount, VaultError::InsufficientBalance);
+            from.debit(amount, clock.unix_timestamp)?;
+            settle_entry(&mut from, close_if_empty, &owner)?;
+
+            let mut to = load_or_init_entry(&leg[1], &recipient_key, mint, &owner, &system_program)?;
+            to.amount = to.amount.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+            to.exit(&crate::ID)?;
+
+            emit!(InternalTransfer { from: owner_key, to: recipient_key, mint: *mint, amount });
+        }
+
+        emit!(BatchOperation { owner: owner_key, count: amounts.len() as u8, fee });
+        Ok(())
+    }
+
+    /// Lets `delegate` move up to `amount` of the caller's `mint` balance
+    /// (`Pubkey::default()` for SOL) through the `delegated_*` instructions,
+    /// until `expires_at` (unix seconds; 0 = no expiry). Like ERC-20
+    /// `approve`, this replaces any previous allowance rather than adding to it.
+    pub fn approve(
+        ctx: Context<Approve>,
+        mint: Pubkey,
+        delegate: Pubkey,
+        amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
         require!(
-            stats.count < MAX_TRANSACTIONS_PER_MINUTE,
-            VaultError::RateLimitExceeded
+            delegate != Pubkey::default() && delegate != owner,
+            VaultError::InvalidDelegate
         );
-        stats.count += 1;
-    } else {
-        stats.window = window;
-        stats.count = 1;
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.owner = owner;
+        allowance.mint = mint;
+        allowance.delegate = delegate;
+        allowance.amount = amount;
+        allowance.expires_at = expires_at;
+        allowance.bump = ctx.bumps.allowance;
+        emit!(AllowanceUpdated { owner, mint, delegate, amount, expires_at });
+        Ok(())
     }
-    Ok(())
-}
 
-// ---------------------------------------------------------------- state
+    /// Owner-signed. Closes the allowance and returns its rent.
+    pub fn revoke(ctx: Context<Revoke>) -> Result<()> {
+        let allowance = &ctx.accounts.allowance;
+        emit!(AllowanceUpdated {
+            owner: allowance.owner,
+            mint: allowance.mint,
+            delegate: allowance.delegate,
+            amount: 0,
+            expires_at: 0,
+        });
+        Ok(())
+    }
 
-#[account]
-#[derive(InitSpace)]
-pub struct Config {
-    pub authority: Pubkey,
-    pub fee_collector: Pubkey,
-    pub salt: [u8; 32],
-    pub fee_lamports: u64,
-    pub bump: u8,
-}
+    /// `withdraw_sol` on the owner's behalf, signed by an approved delegate.
+    /// The lamports go to the delegate, which also pays the fee; the owner's
+    /// entry is left open even if drained. Like every `delegated_*`
+    /// instruction, it spends both the delegate's and the owner's rate quota.
+    pub fn delegated_withdraw_sol(ctx: Context<DelegatedWithdrawSol>, amount: u64) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.delegate.key(),
+            1,
+            &clock,
+        )?;
+        ctx.accounts.owner_stats.bump = ctx.bumps.owner_stats;
+        rate_limit_owner(&ctx.accounts.config, &mut ctx.accounts.owner_stats, &ctx.accounts.owner.key(), 1, &clock)?;
+        check_timelock(
+            &mut ctx.accounts.owner_stats,
+            &mut ctx.accounts.vault_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        require!(ctx.accounts.vault_entry.amount >= amount, VaultError::InsufficientBalance);
+        ctx.accounts.allowance.spend(amount, clock.unix_timestamp)?;
 
-/// One per (owner, mint). `mint == Pubkey::default()` means native SOL.
-#[account]
-#[derive(InitSpace)]
-pub struct VaultEntry {
-    pub owner: Pubkey,
-    pub mint: Pubkey,
-    pub amount: u64,
-    pub bump: u8,
-}
+        charge_fee(
+            fee,
+            ctx.accounts.delegate.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
 
-#[account]
-#[derive(InitSpace)]
-pub struct FeePot {
-    pub accrued: u64,
-    pub bump: u8,
-}
+        let entry = &mut ctx.accounts.vault_entry;
+        entry.debit(amount, clock.unix_timestamp)?;
+        ctx.accounts.mint_totals.debit(amount)?;
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserStats {
-    pub window: i64,
-    pub count: u16,
-    pub bump: u8,
-}
+        pay_from_sol_vault(&ctx.accounts.sol_vault, &ctx.accounts.delegate.to_account_info(), amount)?;
 
-// ---------------------------------------------------------------- contexts
+        emit!(Withdraw { owner: entry.owner, mint: Pubkey::default(), amount, fee });
+        emit!(AllowanceSpent {
+            owner: entry.owner,
+            mint: Pubkey::default(),
+            delegate: ctx.accounts.delegate.key(),
+            amount,
+            remaining: ctx.accounts.allowance.amount,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    #[account(init, payer = authority, space = 8 + Config::INIT_SPACE, seeds = [b"config"], bump)]
-    pub config: Account<'info, Config>,
-    #[account(init, payer = authority, space = 8 + FeePot::INIT_SPACE, seeds = [b"fee_pot"], bump)]
-    pub fee_pot: Account<'info, FeePot>,
-    pub system_program: Program<'info, System>,
-}
+    /// `withdraw_token` on the owner's behalf, paid into the delegate's token
+    /// account. Transfer-hook extra accounts go in `remaining_accounts`.
+    pub fn delegated_withdraw_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, DelegatedWithdrawToken<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        inspect_mint(&ctx.accounts.mint.to_account_info())?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.delegate.key(),
+            1,
+            &clock,
+        )?;
+        ctx.accounts.owner_stats.bump = ctx.bumps.owner_stats;
+        rate_limit_owner(&ctx.accounts.config, &mut ctx.accounts.owner_stats, &ctx.accounts.owner.key(), 1, &clock)?;
+        check_timelock(
+            &mut ctx.accounts.owner_stats,
+            &mut ctx.accounts.vault_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        require!(ctx.accounts.vault_entry.amount >= amount, VaultError::InsufficientBalance);
+        ctx.accounts.allowance.spend(amount, clock.unix_timestamp)?;
 
-#[derive(Accounts)]
-pub struct SetFee<'info> {
-    #[account(mut, address = config.authority @ VaultError::Unauthorized)]
-    pub authority: Signer<'info>,
-    #[account(mut, seeds = [b"config"], bump = config.bump)]
-    pub config: Account<'info, Config>,
-}
+        charge_fee(
+            fee,
+            ctx.accounts.delegate.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let entry = &mut ctx.accounts.vault_entry;
+        entry.debit(amount, clock.unix_timestamp)?;
+        ctx.accounts.mint_totals.debit(amount)?;
+
+        let mint_key = ctx.accounts.mint.key();
+        let seeds: &[&[u8]] = &[b"token_vault", mint_key.as_ref(), &[ctx.bumps.vault_authority]];
+        invoke_transfer_checked(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.delegate_token_account.to_account_info(),
+            ctx.accounts.vault_authority.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            &[seeds],
+        )?;
+
+        emit!(Withdraw { owner: entry.owner, mint: mint_key, amount, fee });
+        emit!(AllowanceSpent {
+            owner: entry.owner,
+            mint: mint_key,
+            delegate: ctx.accounts.delegate.key(),
+            amount,
+            remaining: ctx.accounts.allowance.amount,
+        });
+        Ok(())
+    }
+
+    /// `transfer_internal_sol` on the owner's behalf, signed by an approved
+    /// delegate. The delegate pays the fee and any recipient entry rent.
+    pub fn delegated_transfer_internal_sol(
+        ctx: Context<DelegatedTransferInternalSol>,
+        amount: u64,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_INTERNAL_TRANSFERS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.delegate.key(),
+            1,
+            &clock,
+        )?;
+        ctx.accounts.owner_stats.bump = ctx.bumps.owner_stats;
+        rate_limit_owner(&ctx.accounts.config, &mut ctx.accounts.owner_stats, &ctx.accounts.owner.key(), 1, &clock)?;
+        check_timelock(
+            &mut ctx.accounts.owner_stats,
+            &mut ctx.accounts.from_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        require!(ctx.accounts.from_entry.amount >= amount, VaultError::InsufficientBalance);
+        require!(
+            ctx.accounts.from_entry.key() != ctx.accounts.to_entry.key(),
+            VaultError::SelfTransfer
+        );
+        ctx.accounts.allowance.spend(amount, clock.unix_timestamp)?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.delegate.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let from = &mut ctx.accounts.from_entry;
+        from.debit(amount, clock.unix_timestamp)?;
+        let to = &mut ctx.accounts.to_entry;
+        to.owner = ctx.accounts.recipient.key();
+        to.mint = Pubkey::default();
+        to.amount = to.amount.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        to.bump = ctx.bumps.to_entry;
+
+        emit!(InternalTransfer {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.recipient.key(),
+            mint: Pubkey::default(),
+            amount,
+        });
+        emit!(AllowanceSpent {
+            owner: ctx.accounts.owner.key(),
+            mint: Pubkey::default(),
+            delegate: ctx.accounts.delegate.key(),
+            amount,
+            remaining: ctx.accounts.allowance.amount,
+        });
+        Ok(())
+    }
+
+    /// `transfer_internal_token` on the owner's behalf, signed by an approved
+    /// delegate.
+    pub fn delegated_transfer_internal_token(
+        ctx: Context<DelegatedTransferInternalToken>,
+        amount: u64,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_INTERNAL_TRANSFERS)?;
+        let clock = Clock::get()?;
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.delegate.key(),
+            1,
+            &clock,
+        )?;
+        ctx.accounts.owner_stats.bump = ctx.bumps.owner_stats;
+        rate_limit_owner(&ctx.accounts.config, &mut ctx.accounts.owner_stats, &ctx.accounts.owner.key(), 1, &clock)?;
+        check_timelock(
+            &mut ctx.accounts.owner_stats,
+            &mut ctx.accounts.from_entry,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        require!(ctx.accounts.from_entry.amount >= amount, VaultError::InsufficientBalance);
+        require!(
+            ctx.accounts.from_entry.key() != ctx.accounts.to_entry.key(),
+            VaultError::SelfTransfer
+        );
+        ctx.accounts.allowance.spend(amount, clock.unix_timestamp)?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.delegate.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let mint_key = ctx.accounts.mint.key();
+        let from = &mut ctx.accounts.from_entry;
+        from.debit(amount, clock.unix_timestamp)?;
+        let to = &mut ctx.accounts.to_entry;
+        to.owner = ctx.accounts.recipient.key();
+        to.mint = mint_key;
+        to.amount = to.amount.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        to.bump = ctx.bumps.to_entry;
+
+        emit!(InternalTransfer {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.recipient.key(),
+            mint: mint_key,
+            amount,
+        });
+        emit!(AllowanceSpent {
+            owner: ctx.accounts.owner.key(),
+            mint: mint_key,
+            delegate: ctx.accounts.delegate.key(),
+            amount,
+            remaining: ctx.accounts.allowance.amount,
+        });
+        Ok(())
+    }
+
+    /// Sets the caller's outflow cap for `mint`: withdrawals and internal
+    /// transfers out of that entry may move at most `cap` in any
+    /// `OUTFLOW_WINDOW_SECONDS` span (0 = uncapped). Outflow is kept in hourly
+    /// buckets (see `VaultEntry::rolling_outflow`), so spent capacity comes
+    /// back up to an hour late, never early. Lowering applies at once; raising
+    /// or removing it waits `DAILY_CAP_RAISE_DELAY`.
+    pub fn set_daily_cap(ctx: Context<SetDailyCap>, cap: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let entry = &mut ctx.accounts.vault_entry;
+        let effective_at = entry.schedule_daily_cap(cap, now);
+        emit!(DailyCapUpdated { owner: entry.owner, mint: entry.mint, cap, effective_at });
+        Ok(())
+    }
+
+    /// Opts the caller into time-locked outflows: any single withdrawal or
+    /// internal transfer above the entry's threshold (see
+    /// `set_timelock_threshold`) is refused and must go through
+    /// `request_withdrawal`, which pays out only `delay` seconds later and can
+    /// be cancelled by the owner or `guardian` meanwhile. `delay == 0`
+    /// switches the lock off. Tightening applies at once; anything else waits
+    /// out the current delay, so a stolen key cannot simply turn the lock off.
+    pub fn set_withdrawal_timelock(
+        ctx: Context<SetWithdrawalTimelock>,
+        delay: i64,
+        guardian: Pubkey,
+    ) -> Result<()> {
+        require!((0..=MAX_TIMELOCK_DELAY).contains(&delay), VaultError::InvalidTimelock);
+        let now = Clock::get()?.unix_timestamp;
+        let stats = &mut ctx.accounts.user_stats;
+        stats.bump = ctx.bumps.user_stats;
+        stats.settle_timelock(now);
+        let effective_at = stats.schedule_timelock(delay, guardian, now);
+        emit!(TimelockUpdated {
+            owner: ctx.accounts.owner.key(),
+            delay,
+            guardian,
+            effective_at,
+        });
+        Ok(())
+    }
+
+    /// Sets the largest outflow of one mint, in that mint's base units, that
+    /// skips the caller's timelock. Thresholds are per entry because one raw
+    /// number means very different value across decimals. An entry starts at
+    /// 0, i.e. every outflow is locked while a delay is set. Lowering applies
+    /// at once; raising waits out the current delay.
+    pub fn set_timelock_threshold(ctx: Context<SetTimelockThreshold>, threshold: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stats = &mut ctx.accounts.user_stats;
+        stats.bump = ctx.bumps.user_stats;
+        stats.settle_timelock(now);
+        let entry = &mut ctx.accounts.vault_entry;
+        entry.settle_threshold(now);
+        let effective_at = entry.schedule_threshold(threshold, stats.timelock_delay, now);
+        emit!(TimelockThresholdUpdated { owner: entry.owner, mint: entry.mint, threshold, effective_at });
+        Ok(())
+    }
+
+    /// Starts a time-locked withdrawal of `amount` of `mint`. The balance
+    /// leaves the entry now and is held by the `PendingWithdrawal`, which
+    /// `execute_withdrawal_*` pays out once the owner's delay has elapsed.
+    /// `nonce` only distinguishes concurrent requests.
+    pub fn request_withdrawal(
+        ctx: Context<RequestWithdrawal>,
+        mint: Pubkey,
+        amount: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        require!(amount > 0, VaultError::ZeroAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let stats = &mut ctx.accounts.user_stats;
+        stats.settle_timelock(now);
+        let unlock_at = now.checked_add(stats.timelock_delay).ok_or(VaultError::MathOverflow)?;
+
+        let entry = &mut ctx.accounts.vault_entry;
+        require!(entry.amount >= amount, VaultError::InsufficientBalance);
+        entry.debit(amount, now)?;
+
+        let pending = &mut ctx.accounts.pending_withdrawal;
+        pending.owner = ctx.accounts.owner.key();
+        pending.mint = mint;
+        pending.amount = amount;
+        pending.unlock_at = unlock_at;
+        pending.nonce = nonce;
+        pending.bump = ctx.bumps.pending_withdrawal;
+
+        emit!(WithdrawalRequested { owner: pending.owner, mint, amount, nonce, unlock_at });
+        Ok(())
+    }
+
+    /// Pays out an unlocked SOL `PendingWithdrawal` and closes it. Charged and
+    /// rate-limited like `withdraw_sol`.
+    pub fn execute_withdrawal_sol(ctx: Context<ExecuteWithdrawalSol>) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= ctx.accounts.pending_withdrawal.unlock_at,
+            VaultError::WithdrawalLocked
+        );
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let amount = ctx.accounts.pending_withdrawal.amount;
+        ctx.accounts.mint_totals.debit(amount)?;
+        pay_from_sol_vault(&ctx.accounts.sol_vault, &ctx.accounts.owner.to_account_info(), amount)?;
+
+        emit!(Withdraw { owner: ctx.accounts.owner.key(), mint: Pubkey::default(), amount, fee });
+        Ok(())
+    }
+
+    /// SPL counterpart of `execute_withdrawal_sol`. Transfer-hook extra
+    /// accounts go in `remaining_accounts`, as for `withdraw_token`.
+    pub fn execute_withdrawal_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteWithdrawalToken<'info>>,
+    ) -> Result<()> {
+        require_active(&ctx.accounts.config, PAUSE_WITHDRAWALS)?;
+        inspect_mint(&ctx.accounts.mint.to_account_info())?;
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= ctx.accounts.pending_withdrawal.unlock_at,
+            VaultError::WithdrawalLocked
+        );
+        let fee = current_fee(&ctx.accounts.config, ctx.accounts.price_feed.as_deref(), &clock)?;
+        rate_limit(
+            &ctx.accounts.config,
+            &mut ctx.accounts.user_stats,
+            &mut ctx.accounts.global_stats,
+            &ctx.accounts.owner.key(),
+            1,
+            &clock,
+        )?;
+
+        charge_fee(
+            fee,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.sol_vault.to_account_info(),
+            &mut ctx.accounts.fee_pot,
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let amount = ctx.accounts.pending_withdrawal.amount;
+        ctx.accounts.mint_totals.debit(amount)?;
+
+        let mint_key = ctx.accounts.mint.key();
+        let seeds: &[&[u8]] = &[b"token_vault", mint_key.as_ref(), &[ctx.bumps.vault_authority]];
+        invoke_transfer_checked(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.owner_token_account.to_account_info(),
+            ctx.accounts.vault_authority.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            &[seeds],
+        )?;
+
+        emit!(Withdraw { owner: ctx.accounts.owner.key(), mint: mint_key, amount, fee });
+        Ok(())
+    }
+
+    /// Signed by the owner or their guardian. Returns a pending withdrawal's
+    /// amount to the owner's entry (recreating it if it was closed, at the
+    /// canceller's expense) and closes the request.
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>) -> Result<()> {
+        let canceller = ctx.accounts.canceller.key();
+        let owner = ctx.accounts.owner.key();
+        if canceller != owner {
+            require!(
+                stored_guardian(&ctx.accounts.owner_stats)? == Some(canceller),
+                VaultError::Unauthorized
+            );
+        }
+
+        let pending = &ctx.accounts.pending_withdrawal;
+        let (mint, amount, nonce) = (pending.mint, pending.amount, pending.nonce);
+        let entry = &mut ctx.accounts.vault_entry;
+        entry.owner = owner;
+        entry.mint = mint;
+        entry.amount = entry.amount.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        entry.bump = ctx.bumps.vault_entry;
+
+        emit!(WithdrawalCancelled { owner, mint, amount, nonce, by: canceller });
+        Ok(())
+    }
+
+    /// Closes a drained `VaultEntry` and returns its rent to the owner. A
+    /// later deposit of the same mint simply recreates it. Never while a daily
+    /// cap is set or scheduled.
+    pub fn close_vault_entry(ctx: Context<CloseVaultEntry>) -> Result<()> {
+        let entry = &mut ctx.accounts.vault_entry;
+        // The recreated entry would start uncapped, skipping the raise delay.
+        entry.settle_daily_cap(Clock::get()?.unix_timestamp);
+        require!(!entry.caps_outflow(), VaultError::DailyCapActive);
+        emit!(VaultEntryClosed { owner: entry.owner, mint: entry.mint });
+        Ok(())
+    }
+
+    /// Closes the caller's `UserStats` and returns its rent. Only once the
+    /// current rate-limit window has passed, so close-and-recreate cannot be
+    /// used to reset a live count, and never while a withdrawal lock is set.
+    pub fn close_user_stats(ctx: Context<CloseUserStats>) -> Result<()> {
+        let window = window_start(Clock::get()?.unix_timestamp, ctx.accounts.config.rate_window_secs);
+        let stats = &ctx.accounts.user_stats;
+        require!(stats.window < window, VaultError::StatsWindowOpen);
+        // Closing would silently drop the owner's withdrawal lock.
+        require!(
+            stats.timelock_delay == 0 && stats.pending_timelock_at == 0,
+            VaultError::TimelockActive
+        );
+        Ok(())
+    }
+
+    /// Permissionless proof of reserves for one mint: what the ledger owes
+    /// (`MintTotals.total`, plus `FeePot.accrued` for native SOL) against what
+    /// custody holds (`sol_vault` above its rent floor, or the mint's vault
+    /// ATA). Always emits `SolvencyReport`; fails if custody is short.
+    /// `vault_token_account` is required for an SPL mint and ignored for SOL.
+    pub fn assert_solvency(ctx: Context<AssertSolvency>, mint: Pubkey) -> Result<()> {
+        let mut liabilities = ctx.accounts.mint_totals.total;
+        let custody = if mint == Pubkey::default() {
+            liabilities = liabilities
+                .checked_add(ctx.accounts.fee_pot.accrued)
+                .ok_or(VaultError::MathOverflow)?;
+            let vault = ctx.accounts.sol_vault.to_account_info();
+            let floor = Rent::get()?.minimum_balance(vault.data_len());
+            vault.lamports().saturating_sub(floor)
+        } else {
+            let account = ctx
+                .accounts
+                .vault_token_account
+                .as_ref()
+                .ok_or(VaultError::InvalidCustodyAccount)?;
+            let (vault_authority, _) =
+                Pubkey::find_program_address(&[b"token_vault", mint.as_ref()], &crate::ID);
+            let expected = get_associated_token_address_with_program_id(
+                &vault_authority,
+                &mint,
+                account.to_account_info().owner,
+            );
+            require_keys_eq!(account.key(), expected, VaultError::InvalidCustodyAccount);
+            require_keys_eq!(account.mint, mint, VaultError::MintMismatch);
+            account.amount
+        };
+
+        emit!(SolvencyReport { mint, liabilities, custody, solvent: custody >= liabilities });
+        require!(custody >= liabilities, VaultError::Insolvent);
+        Ok(())
+    }
+
+    /// `collectFees()` — sweeps the accrued fee pot to the configured collector.
+    pub fn collect_fees(ctx: Context<CollectFees>) -> Result<()> {
+        let amount = ctx.accounts.fee_pot.accrued;
+        require!(amount > 0, VaultError::NothingToCollect);
+        ctx.accounts.fee_pot.accrued = 0;
+
+        pay_from_sol_vault(
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.fee_collector.to_account_info(),
+            amount,
+        )?;
+
+        emit!(FeesCollected { to: ctx.accounts.fee_collector.key(), amount });
+        Ok(())
+    }
+}
+
+/// Emergency stop, checked first thing by every instruction in a pausable class.
+fn require_active(config: &Config, class: u8) -> Result<()> {
+    require!(config.paused & class == 0, VaultError::Paused);
+    Ok(())
+}
+
+/// The single fee-computation path. Fixed mode returns `fee_lamports`; oracle
+/// mode prices `fee_usd_cents` off the Pyth SOL/USD account and falls back to
+/// `fee_lamports` when that price is stale or its confidence band too wide.
+/// The account itself must be the configured one — a caller cannot dodge the
+/// oracle by omitting it or substituting another feed.
+fn current_fee(config: &Config, price_feed: Option<&AccountInfo>, clock: &Clock) -> Result<u64> {
+    if config.price_feed == Pubkey::default() {
+        return Ok(config.fee_lamports);
+    }
+    let feed = price_feed.ok_or(VaultError::PriceFeedMissing)?;
+    require_keys_eq!(feed.key(), config.price_feed, VaultError::PriceFeedMismatch);
+    require_keys_eq!(*feed.owner, PYTH_RECEIVER_ID, VaultError::PriceFeedMismatch);
+    let price = PythPrice::parse(&feed.try_borrow_data()?)?;
+    Ok(price
+        .fee_lamports(
+            config.fee_usd_cents,
+            clock.unix_timestamp,
+            config.max_price_age,
+            config.max_conf_bps,
+        )
+        .unwrap_or(config.fee_lamports))
+}
+
+/// Moves `fee` from the payer's wallet into `sol_vault` and books it in the
+/// fee pot. Mirrors `_chargeFeeFromWallet`.
+fn charge_fee<'info>(
+    fee: u64,
+    payer: AccountInfo<'info>,
+    sol_vault: AccountInfo<'info>,
+    fee_pot: &mut Account<'info, FeePot>,
+    system_program: AccountInfo<'info>,
+) -> Result<()> {
+    system_program::transfer(
+        CpiContext::new(system_program, system_program::Transfer { from: payer, to: sol_vault }),
+        fee,
+    )?;
+    fee_pot.accrued = fee_pot.accrued.checked_add(fee).ok_or(VaultError::MathOverflow)?;
+    Ok(())
+}
+
+/// The only way lamports leave `sol_vault`. The account is program-owned, so
+/// the debit is plain lamport arithmetic, and it may never dip below its
+/// rent-exempt minimum — if the ledger ever asked for that, the ledger is
+/// wrong and the withdrawal must fail rather than let the runtime reap custody.
+fn pay_from_sol_vault(sol_vault: &Account<SolVault>, to: &AccountInfo, amount: u64) -> Result<()> {
+    let vault = sol_vault.to_account_info();
+    let floor = Rent::get()?.minimum_balance(vault.data_len());
+    let remaining = vault.lamports().checked_sub(amount).ok_or(VaultError::CustodyShortfall)?;
+    require!(remaining >= floor, VaultError::CustodyShortfall);
+    **vault.try_borrow_mut_lamports()? = remaining;
+    let credited = to.lamports().checked_add(amount).ok_or(VaultError::MathOverflow)?;
+    **to.try_borrow_mut_lamports()? = credited;
+    Ok(())
+}
+
+/// The fields of a Pyth `PriceUpdateV2` account that the fee needs. Parsed by
+/// hand: the receiver SDK's dependency tree does not build alongside this
+/// Anchor version, and the layout is small and stable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PythPrice {
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    /// `VerificationLevel::Full`. Partially verified updates are never used.
+    pub fully_verified: bool,
+}
+
+impl PythPrice {
+    /// `discriminator | write_authority | verification_level | feed_id |
+    /// price | conf | exponent | publish_time | ...`, Borsh-encoded.
+    /// `verification_level` is `0u8, num_signatures` (Partial) or `1u8` (Full).
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= 8 && data[..8] == PYTH_PRICE_UPDATE_DISCRIMINATOR,
+            VaultError::InvalidPriceFeed
+        );
+        let mut at = 8 + 32;
+        let fully_verified = match data.get(at) {
+            Some(0) => {
+                at += 2;
+                false
+            }
+            Some(1) => {
+                at += 1;
+                true
+            }
+            _ => return err!(VaultError::InvalidPriceFeed),
+        };
+        at += 32; // feed_id; the account key is what `Config` pins
+        let field = |at: usize, len: usize| -> Result<&[u8]> {
+            data.get(at..at + len).ok_or_else(|| error!(VaultError::InvalidPriceFeed))
+        };
+        Ok(Self {
+            price: i64::from_le_bytes(field(at, 8)?.try_into().unwrap()),
+            conf: u64::from_le_bytes(field(at + 8, 8)?.try_into().unwrap()),
+            exponent: i32::from_le_bytes(field(at + 16, 4)?.try_into().unwrap()),
+            publish_time: i64::from_le_bytes(field(at + 20, 8)?.try_into().unwrap()),
+            fully_verified,
+        })
+    }
+
+    /// `_getDynamicFeeInWei` on lamports: `cents * 10^(9 - 2 - expo) / price`.
+    /// `None` means "do not charge against this price" — unverified, stale,
+    /// non-positive, too uncertain, or out of range.
+    pub fn fee_lamports(
+        &self,
+        fee_usd_cents: u64,
+        now: i64,
+        max_age: u64,
+        max_conf_bps: u16,
+    ) -> Option<u64> {
+        if !self.fully_verified || self.price <= 0 {
+            return None;
+        }
+        let age = now.checked_sub(self.publish_time)?;
+        if age < 0 || age as u64 > max_age {
+            return None;
+        }
+        let price = self.price as u128;
+        if (self.conf as u128) * (BPS_DENOM as u128) > price * (max_conf_bps as u128) {
+            return None;
+        }
+        let scale = 7i32.checked_sub(self.exponent)?;
+        let fee = if scale >= 0 {
+            (fee_usd_cents as u128).checked_mul(10u128.checked_pow(scale as u32)?)? / price
+        } else {
+            (fee_usd_cents as u128) / 10u128.checked_pow(scale.unsigned_abs())?.checked_mul(price)?
+        };
+        u64::try_from(fee).ok()
+    }
+}
+
+/// Writes back a `VaultEntry` touched by an outflow — or, when the caller opted
+/// in and the balance is now zero, closes it to `owner`. The EVM
+/// `_removeTokenIfZero`, with the rent going back to whoever paid it. An
+/// entry with a daily cap stays open, as in `close_vault_entry`.
+fn settle_entry<'info>(
+    entry: &mut Account<'info, VaultEntry>,
+    close_if_empty: bool,
+    owner: &AccountInfo<'info>,
+) -> Result<()> {
+    if close_if_empty && entry.amount == 0 && !entry.caps_outflow() {
+        emit!(VaultEntryClosed { owner: entry.owner, mint: entry.mint });
+        return entry.close(owner.clone());
+    }
+    entry.exit(&crate::ID)
+}
+
+/// Mirrors `_checkAndUpdateRateLimit`, made tunable. Takes `weight` units
+/// from the caller's quota — unless `user` is on the exemption list — and,
+/// when enabled, from the all-users quota in `GlobalStats`.
+fn rate_limit(
+    config: &Config,
+    stats: &mut Account<UserStats>,
+    global: &mut Account<GlobalStats>,
+    user: &Pubkey,
+    weight: usize,
+    clock: &Clock,
+) -> Result<()> {
+    let weight = u32::try_from(weight).map_err(|_| VaultError::MathOverflow)?;
+    let window = window_start(clock.unix_timestamp, config.rate_window_secs);
+    take_user_quota(config, stats, user, weight, window)?;
+    if config.global_rate_quota > 0 {
+        let global: &mut GlobalStats = global;
+        require!(
+            take_quota(&mut global.window, &mut global.count, window, weight, config.global_rate_quota),
+            VaultError::GlobalRateLimitExceeded
+        );
+    }
+    Ok(())
+}
+
+/// The per-user half of `rate_limit`, charged to the balance owner on
+/// delegated outflows. Otherwise an owner, or a thief with the owner's key,
+/// could fan out through several approved delegates, each with a fresh quota.
+fn rate_limit_owner(
+    config: &Config,
+    owner_stats: &mut UserStats,
+    owner: &Pubkey,
+    weight: usize,
+    clock: &Clock,
+) -> Result<()> {
+    let weight = u32::try_from(weight).map_err(|_| VaultError::MathOverflow)?;
+    let window = window_start(clock.unix_timestamp, config.rate_window_secs);
+    take_user_quota(config, owner_stats, owner, weight, window)
+}
+
+/// Takes `weight` from `user`'s quota unless they are rate-exempt.
+fn take_user_quota(config: &Config, stats: &mut UserStats, user: &Pubkey, weight: u32, window: i64) -> Result<()> {
+    if !config.rate_exempt.contains(user) {
+        require!(
+            take_quota(&mut stats.window, &mut stats.count, window, weight, config.rate_quota),
+            VaultError::RateLimitExceeded
+        );
+    }
+    Ok(())
+}
+
+/// Start of the fixed window containing `now`.
+fn window_start(now: i64, window_secs: i64) -> i64 {
+    now - now.rem_euclid(window_secs)
+}
+
+/// Fixed-window counter: a new `window` resets `count`. Returns false, leaving
+/// the counter untouched, if `weight` more would exceed `quota`.
+fn take_quota(stored: &mut i64, count: &mut u32, window: i64, weight: u32, quota: u32) -> bool {
+    let used = if *stored == window { *count } else { 0 };
+    match used.checked_add(weight) {
+        Some(total) if total <= quota => {
+            *stored = window;
+            *count = total;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Refuses an outflow of `amount` from `entry` that the owner's withdrawal
+/// lock routes through `request_withdrawal`.
+fn check_timelock(stats: &mut UserStats, entry: &mut VaultEntry, amount: u64, now: i64) -> Result<()> {
+    stats.settle_timelock(now);
+    entry.settle_threshold(now);
+    require!(
+        !stats.requires_timelock(amount, entry.timelock_threshold),
+        VaultError::WithdrawalTimelocked
+    );
+    Ok(())
+}
+
+/// The guardian recorded in an owner's `UserStats`, if any. A scheduled
+/// guardian change does not count until it takes effect.
+fn stored_guardian(owner_stats: &AccountInfo) -> Result<Option<Pubkey>> {
+    if owner_stats.owner != &crate::ID {
+        return Ok(None);
+    }
+    let mut stats = UserStats::try_deserialize(&mut &owner_stats.try_borrow_data()?[..])?;
+    stats.settle_timelock(Clock::get()?.unix_timestamp);
+    Ok(Some(stats.guardian).filter(|g| *g != Pubkey::default()))
+}
+
+/// Mirrors `_validateMultiTokenInput`: 1..=`MAX_BATCH_SIZE` legs whose
+/// accounts fit in one transaction, no zero amounts, and exactly
+/// `expected_accounts` remaining accounts for the batch.
+fn validate_batch(amounts: &[u64], remaining: usize, expected_accounts: usize) -> Result<()> {
+    require!(
+        !amounts.is_empty()
+            && amounts.len() <= MAX_BATCH_SIZE
+            && expected_accounts <= MAX_BATCH_REMAINING_ACCOUNTS,
+        VaultError::InvalidBatchSize
+    );
+    require!(remaining == expected_accounts, VaultError::BatchAccountsMismatch);
+    require!(amounts.iter().all(|a| *a > 0), VaultError::ZeroAmount);
+    Ok(())
+}
+
+/// Checks a batch leg's `MintPolicy` address. An existing policy is checked
+/// against its stored bump; only an absent one needs the bump search.
+fn check_policy_address(info: &AccountInfo, mint: &Pubkey) -> Result<()> {
+    let expected = if info.owner == &crate::ID {
+        let policy = MintPolicy::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        Pubkey::create_program_address(&[b"mint_policy", mint.as_ref(), &[policy.bump]], &crate::ID)
+            .map_err(|_| VaultError::InvalidMintPolicy)?
+    } else {
+        Pubkey::find_program_address(&[b"mint_policy", mint.as_ref()], &crate::ID).0
+    };
+    require_keys_eq!(info.key(), expected, VaultError::InvalidMintPolicy);
+    Ok(())
+}
+
+/// Applies the mint's `MintPolicy` to an SPL deposit that credited `amount`
+/// (what custody received, not what was sent). A policy,
+/// once created, always applies — open mode only admits mints that have none.
+/// `policy` is the `[b"mint_policy", mint]` address, already checked by the
+/// caller; an uninitialised one means "no policy".
+fn check_mint_policy(config: &Config, policy: &AccountInfo, amount: u64) -> Result<()> {
+    if policy.owner != &crate::ID {
+        require!(config.open_mode, VaultError::MintNotAllowed);
+        return Ok(());
+    }
+    let policy = MintPolicy::try_deserialize(&mut &policy.try_borrow_data()?[..])?;
+    require!(policy.allowed, VaultError::MintNotAllowed);
+    require!(amount >= policy.min_deposit, VaultError::BelowMinDeposit);
+    require!(
+        policy.max_deposit == 0 || amount <= policy.max_deposit,
+        VaultError::AboveMaxDeposit
+    );
+    Ok(())
+}
+
+/// Refuses Token-2022 mints whose semantics the ledger cannot honour, and
+/// reports whether the mint has a transfer hook (whose extra accounts the
+/// caller then has to supply). Legacy SPL mints have no extensions.
+///
+/// * NonTransferable — custody could never pay it back out.
+/// * ConfidentialTransferMint — balances can move as encrypted amounts the
+///   ledger never sees.
+/// * PermanentDelegate — a third party can pull tokens out of custody
+///   without the vault authority's signature.
+fn inspect_mint(mint: &AccountInfo) -> Result<bool> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    for extension in state.get_extension_types()? {
+        match extension {
+            ExtensionType::NonTransferable => return err!(VaultError::NonTransferableMint),
+            ExtensionType::ConfidentialTransferMint => return err!(VaultError::ConfidentialMint),
+            ExtensionType::PermanentDelegate => return err!(VaultError::PermanentDelegateMint),
+            _ => {}
+        }
+    }
+    Ok(transfer_hook::get_program_id(&state).is_some())
+}
+
+/// What custody actually gained across a deposit transfer. This, not the
+/// requested amount, is what the ledger credits.
+fn received_delta(before: u64, after: u64) -> Result<u64> {
+    let received = after.checked_sub(before).ok_or(VaultError::MathOverflow)?;
+    require!(received > 0, VaultError::ZeroAmount);
+    Ok(received)
+}
+
+/// Loads the canonical custody account for `mint`: the associated token
+/// account of its `[b"token_vault", mint]` authority under `token_program`.
+/// Anything else — even a token account of the right mint — is rejected, so
+/// ledger credit always corresponds to tokens the program controls.
+fn custody_account<'info>(
+    info: &'info AccountInfo<'info>,
+    vault_authority: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<InterfaceAccount<'info, TokenAccount>> {
+    let expected = get_associated_token_address_with_program_id(vault_authority, mint, token_program);
+    require_keys_eq!(info.key(), expected, VaultError::InvalidCustodyAccount);
+    let account = InterfaceAccount::<TokenAccount>::try_from(info)?;
+    require_keys_eq!(account.mint, *mint, VaultError::MintMismatch);
+    require_keys_eq!(account.owner, *vault_authority, VaultError::InvalidCustodyAccount);
+    Ok(account)
+}
+
+/// Loads an existing `VaultEntry` from `remaining_accounts`, checking it is
+/// the `[b"vault", owner, mint]` PDA and that its fields agree.
+fn load_entry<'info>(
+    info: &'info AccountInfo<'info>,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Account<'info, VaultEntry>> {
+    require!(info.is_writable, VaultError::InvalidVaultEntry);
+    let entry = Account::<VaultEntry>::try_from(info)?;
+    let expected = Pubkey::create_program_address(
+        &[b"vault", owner.as_ref(), mint.as_ref(), &[entry.bump]],
+        &crate::ID,
+    )
+    .map_err(|_| VaultError::InvalidVaultEntry)?;
+    require_keys_eq!(info.key(), expected, VaultError::InvalidVaultEntry);
+    require_keys_eq!(entry.owner, *owner, VaultError::InvalidVaultEntry);
+    require_keys_eq!(entry.mint, *mint, VaultError::InvalidVaultEntry);
+    Ok(entry)
+}
+
+/// `init_if_needed` for a `VaultEntry` that arrives through
+/// `remaining_accounts`, where Anchor's account constraints cannot reach. The
+/// PDA derivation is checked here, so a caller cannot substitute another
+/// owner's or another mint's entry. An existing entry is checked against its
+/// stored bump; only creation pays for the bump search.
+fn load_or_init_entry<'info>(
+    info: &'info AccountInfo<'info>,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<Account<'info, VaultEntry>> {
+    if info.owner == &crate::ID {
+        return load_entry(info, owner, mint);
+    }
+    let (expected, bump) =
+        Pubkey::find_program_address(&[b"vault", owner.as_ref(), mint.as_ref()], &crate::ID);
+    require_keys_eq!(info.key(), expected, VaultError::InvalidVaultEntry);
+    require!(info.is_writable, VaultError::InvalidVaultEntry);
+
+    create_pda(
+        info,
+        &[b"vault", owner.as_ref(), mint.as_ref(), &[bump]],
+        8 + VaultEntry::INIT_SPACE,
+        payer,
+        system_program,
+    )?;
+    let mut entry = Account::<VaultEntry>::try_from_unchecked(info)?;
+    entry.owner = *owner;
+    entry.mint = *mint;
+    entry.amount = 0;
+    entry.bump = bump;
+    Ok(entry)
+}
+
+/// Loads an existing `MintTotals` from `remaining_accounts`, checking it is
+/// the `[b"mint_totals", mint]` PDA.
+fn load_totals<'info>(info: &'info AccountInfo<'info>, mint: &Pubkey) -> Result<Account<'info, MintTotals>> {
+    require!(info.is_writable, VaultError::InvalidMintTotals);
+    let totals = Account::<MintTotals>::try_from(info)?;
+    let expected = Pubkey::create_program_address(
+        &[b"mint_totals", mint.as_ref(), &[totals.bump]],
+        &crate::ID,
+    )
+    .map_err(|_| VaultError::InvalidMintTotals)?;
+    require_keys_eq!(info.key(), expected, VaultError::InvalidMintTotals);
+    require_keys_eq!(totals.mint, *mint, VaultError::InvalidMintTotals);
+    Ok(totals)
+}
+
+/// `init_if_needed` for a `MintTotals` in `remaining_accounts`; the first
+/// depositor of a mint pays its rent, as with the custody ATA.
+fn load_or_init_totals<'info>(
+    info: &'info AccountInfo<'info>,
+    mint: &Pubkey,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<Account<'info, MintTotals>> {
+    if info.owner == &crate::ID {
+        return load_totals(info, mint);
+    }
+    let (expected, bump) = Pubkey::find_program_address(&[b"mint_totals", mint.as_ref()], &crate::ID);
+    require_keys_eq!(info.key(), expected, VaultError::InvalidMintTotals);
+    require!(info.is_writable, VaultError::InvalidMintTotals);
+
+    create_pda(
+        info,
+        &[b"mint_totals", mint.as_ref(), &[bump]],
+        8 + MintTotals::INIT_SPACE,
+        payer,
+        system_program,
+    )?;
+    let mut totals = Account::<MintTotals>::try_from_unchecked(info)?;
+    totals.mint = *mint;
+    totals.total = 0;
+    totals.cap = 0;
+    totals.bump = bump;
+    Ok(totals)
+}
+
+/// Creates the program-owned PDA `info` (signed for by `signer`) with `space`
+/// bytes. Same sequence Anchor's `init` emits: fund (topping up if someone
+/// already sent lamports to the address), allocate, assign.
+fn create_pda<'info>(
+    info: &AccountInfo<'info>,
+    signer: &[&[u8]],
+    space: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let required = Rent::get()?.minimum_balance(space);
+    let current = info.lamports();
+    if current == 0 {
+        return system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::CreateAccount { from: payer.clone(), to: info.clone() },
+                &[signer],
+            ),
+            required,
+            space as u64,
+            &crate::ID,
+        );
+    }
+    if required > current {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer { from: payer.clone(), to: info.clone() },
+            ),
+            required - current,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Allocate { account_to_allocate: info.clone() },
+            &[signer],
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Assign { account_to_assign: info.clone() },
+            &[signer],
+        ),
+        &crate::ID,
+    )
+}
+
+// ---------------------------------------------------------------- state
+
+#[account]
+#[derive(InitSpace)]
+pub struct Config {
+    pub authority: Pubkey,
+    /// Set by `propose_authority`, cleared by `accept_authority`.
+    pub pending_authority: Pubkey,
+    pub fee_collector: Pubkey,
+    pub salt: [u8; 32],
+    /// Fixed fee, and the fallback when the oracle price is unusable.
+    pub fee_lamports: u64,
+    /// Pyth SOL/USD `PriceUpdateV2` account. `Pubkey::default()` = fixed mode.
+    pub price_feed: Pubkey,
+    /// USD fee target in cents, mirroring `USD_FEE_CENTS`.
+    pub fee_usd_cents: u64,
+    /// Oldest acceptable `publish_time`, in seconds before now.
+    pub max_price_age: u64,
+    /// Widest acceptable confidence interval, in bps of the price.
+    pub max_conf_bps: u16,
+    /// Accept SPL mints that have no `MintPolicy`. See `set_open_mode`.
+    pub open_mode: bool,
+    /// Rate-limit window length in seconds.
+    pub rate_window_secs: i64,
+    /// Weight units each user may spend per window.
+    pub rate_quota: u32,
+    /// Weight units all users together may spend per window; 0 = off.
+    pub global_rate_quota: u32,
+    /// Wallets the per-user quota does not apply to. See `set_rate_exemption`.
+    #[max_len(MAX_RATE_EXEMPT)]
+    pub rate_exempt: Vec<Pubkey>,
+    /// `PAUSE_*` bitmask; a set bit halts that instruction class.
+    pub paused: u8,
+    pub bump: u8,
+}
+
+/// One per (owner, mint). `mint == Pubkey::default()` means native SOL.
+#[account]
+#[derive(InitSpace)]
+pub struct VaultEntry {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Owner-set outflow ceiling per `OUTFLOW_WINDOW_SECONDS`; 0 = uncapped.
+    pub daily_cap: u64,
+    /// A raised cap waiting to apply at `pending_cap_at` (0 = none).
+    pub pending_daily_cap: u64,
+    pub pending_cap_at: i64,
+    /// Outflow per `OUTFLOW_BUCKET_SECONDS` bucket, a ring indexed by bucket
+    /// number mod `OUTFLOW_BUCKETS`; `outflow_bucket` is the newest one
+    /// cleared for use (see `rolling_outflow`).
+    pub outflow_bucket: i64,
+    pub outflow: [u64; OUTFLOW_BUCKETS],
+    /// Largest outflow that skips the owner's timelock, in this mint's base
+    /// units (see `set_timelock_threshold`).
+    pub timelock_threshold: u64,
+    /// A raised threshold waiting to apply at `pending_threshold_at` (0 = none).
+    pub pending_timelock_threshold: u64,
+    pub pending_threshold_at: i64,
+    pub bump: u8,
+}
+
+impl VaultEntry {
+    /// Every outflow goes through here: counts `amount` against the daily cap,
+    /// then takes it off the balance. Outflow is counted even while uncapped,
+    /// so a cap set mid-window sees what already left.
+    pub fn debit(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.settle_daily_cap(now);
+        let used = self.rolling_outflow(now).checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        require!(
+            self.daily_cap == 0 || used <= self.daily_cap,
+            VaultError::DailyCapExceeded
+        );
+        self.amount = self.amount.checked_sub(amount).ok_or(VaultError::MathOverflow)?;
+        let slot = &mut self.outflow[Self::outflow_slot(now)];
+        *slot = slot.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// An upper bound on the outflow of the `OUTFLOW_WINDOW_SECONDS` ending at
+    /// `now`: the sum of every bucket overlapping that span, i.e. the current
+    /// one and the `OUTFLOW_BUCKETS - 1` before it, each in full. Buckets that
+    /// have aged out are cleared first, so the ring never holds anything older.
+    pub fn rolling_outflow(&mut self, now: i64) -> u64 {
+        let bucket = now.div_euclid(OUTFLOW_BUCKET_SECONDS);
+        let fresh = (bucket - self.outflow_bucket).clamp(0, OUTFLOW_BUCKETS as i64);
+        for b in bucket - fresh + 1..=bucket {
+            self.outflow[b.rem_euclid(OUTFLOW_BUCKETS as i64) as usize] = 0;
+        }
+        self.outflow_bucket = self.outflow_bucket.max(bucket);
+        self.outflow.iter().fold(0u64, |sum, b| sum.saturating_add(*b))
+    }
+
+    fn outflow_slot(now: i64) -> usize {
+        now.div_euclid(OUTFLOW_BUCKET_SECONDS).rem_euclid(OUTFLOW_BUCKETS as i64) as usize
+    }
+
+    /// Whether a daily cap is set or scheduled. Such an entry is never closed:
+    /// its replacement would start uncapped.
+    pub fn caps_outflow(&self) -> bool {
+        self.daily_cap != 0 || self.pending_cap_at != 0
+    }
+
+    /// Applies a raised cap whose wait is over.
+    pub fn settle_daily_cap(&mut self, now: i64) {
+        if self.pending_cap_at != 0 && now >= self.pending_cap_at {
+            self.daily_cap = self.pending_daily_cap;
+            self.pending_cap_at = 0;
+        }
+    }
+
+    /// Lowers the cap now, or schedules a raise (0 counts as the highest cap)
+    /// `DAILY_CAP_RAISE_DELAY` out, replacing any earlier scheduled raise. A
+    /// raise already due is applied first, so `cap` is compared with the cap
+    /// actually in force. Returns when `cap` takes effect.
+    pub fn schedule_daily_cap(&mut self, cap: u64, now: i64) -> i64 {
+        self.settle_daily_cap(now);
+        let lowering = cap != 0 && (self.daily_cap == 0 || cap <= self.daily_cap);
+        if lowering {
+            self.daily_cap = cap;
+            self.pending_cap_at = 0;
+            return now;
+        }
+        self.pending_daily_cap = cap;
+        self.pending_cap_at = now + DAILY_CAP_RAISE_DELAY;
+        self.pending_cap_at
+    }
+
+    /// Applies a raised timelock threshold whose wait is over.
+    pub fn settle_threshold(&mut self, now: i64) {
+        if self.pending_threshold_at != 0 && now >= self.pending_threshold_at {
+            self.timelock_threshold = self.pending_timelock_threshold;
+            self.pending_threshold_at = 0;
+        }
+    }
+
+    /// Lowers the threshold now, or schedules a raise `delay` (the owner's
+    /// current timelock delay) out, replacing any earlier scheduled raise.
+    /// Returns when `threshold` takes effect.
+    pub fn schedule_threshold(&mut self, threshold: u64, delay: i64, now: i64) -> i64 {
+        if threshold <= self.timelock_threshold || delay == 0 {
+            self.timelock_threshold = threshold;
+            self.pending_threshold_at = 0;
+            return now;
+        }
+        self.pending_timelock_threshold = threshold;
+        self.pending_threshold_at = now + delay;
+        self.pending_threshold_at
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FeePot {
+    pub accrued: u64,
+    pub bump: u8,
+}
+
+/// Authority-curated deposit rules for one SPL mint, seeds
+/// `[b"mint_policy", mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct MintPolicy {
+    pub mint: Pubkey,
+    pub allowed: bool,
+    /// Largest single deposit; 0 = uncapped.
+    pub max_deposit: u64,
+    /// Smallest single deposit; 0 = no minimum.
+    pub min_deposit: u64,
+    pub bump: u8,
+}
+
+/// Ledger total for one mint, seeds `[b"mint_totals", mint]`
+/// (`Pubkey::default()` for native SOL): the sum of every `VaultEntry` of
+/// that mint, i.e. what custody owes depositors. Moved by every deposit and
+/// withdrawal; internal transfers leave it alone.
+#[account]
+#[derive(InitSpace)]
+pub struct MintTotals {
+    pub mint: Pubkey,
+    pub total: u64,
+    /// Guarded-launch ceiling on `total`; 0 = uncapped. See `set_tvl_cap`.
+    pub cap: u64,
+    pub bump: u8,
+}
+
+impl MintTotals {
+    /// Books a deposit, refusing one that would lift `total` past `cap`.
+    pub fn credit(&mut self, amount: u64) -> Result<()> {
+        let total = self.total.checked_add(amount).ok_or(VaultError::MathOverflow)?;
+        require!(self.cap == 0 || total <= self.cap, VaultError::TvlCapExceeded);
+        self.total = total;
+        Ok(())
+    }
+
+    /// Books a withdrawal. The cap never applies on the way out.
+    pub fn debit(&mut self, amount: u64) -> Result<()> {
+        self.total = self.total.checked_sub(amount).ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// A delegate's spending right over one owner's balance of one mint, seeds
+/// `[b"allow", owner, mint, delegate]`. See `approve`.
+#[account]
+#[derive(InitSpace)]
+pub struct Allowance {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub delegate: Pubkey,
+    /// What the delegate may still move.
+    pub amount: u64,
+    /// Unix seconds after which the allowance is void; 0 = no expiry.
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+impl Allowance {
+    /// Draws `amount` down at time `now`.
+    pub fn spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        require!(self.expires_at == 0 || now < self.expires_at, VaultError::AllowanceExpired);
+        self.amount = self.amount.checked_sub(amount).ok_or(VaultError::AllowanceExceeded)?;
+        Ok(())
+    }
+}
+
+/// Native-SOL custody. Created by `initialize` and owned by this program, so
+/// withdrawals and fee sweeps can debit it directly. Its rent-exempt minimum
+/// is never part of any balance; `pay_from_sol_vault` keeps it in place.
+#[account]
+#[derive(InitSpace)]
+pub struct SolVault {
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserStats {
+    /// Start of the window `count` belongs to, in unix seconds.
+    pub window: i64,
+    pub count: u32,
+    /// Withdrawal lock (see `set_withdrawal_timelock`); `timelock_delay == 0`
+    /// means off. Thresholds live on each `VaultEntry`.
+    pub timelock_delay: i64,
+    /// May cancel pending withdrawals alongside the owner.
+    pub guardian: Pubkey,
+    /// A loosening change waiting out the current delay; applies once
+    /// `pending_timelock_at` (0 = none) has passed.
+    pub pending_timelock_delay: i64,
+    pub pending_guardian: Pubkey,
+    pub pending_timelock_at: i64,
+    pub bump: u8,
+}
+
+impl UserStats {
+    /// Applies a scheduled change whose waiting period is over.
+    pub fn settle_timelock(&mut self, now: i64) {
+        if self.pending_timelock_at != 0 && now >= self.pending_timelock_at {
+            self.timelock_delay = self.pending_timelock_delay;
+            self.guardian = self.pending_guardian;
+            self.pending_timelock_at = 0;
+        }
+    }
+
+    /// Applies the new settings now if they only tighten the lock (or there is
+    /// no lock yet), else schedules them one current delay out. Either way any
+    /// earlier scheduled change is replaced. Returns when they take effect.
+    pub fn schedule_timelock(&mut self, delay: i64, guardian: Pubkey, now: i64) -> i64 {
+        let tightening =
+            self.timelock_delay == 0 || (delay >= self.timelock_delay && guardian == self.guardian);
+        if tightening {
+            self.timelock_delay = delay;
+            self.guardian = guardian;
+            self.pending_timelock_at = 0;
+            return now;
+        }
+        self.pending_timelock_delay = delay;
+        self.pending_guardian = guardian;
+        self.pending_timelock_at = now + self.timelock_delay;
+        self.pending_timelock_at
+    }
+
+    /// Whether an outflow of `amount` from an entry with `threshold` must go
+    /// through `request_withdrawal`.
+    pub fn requires_timelock(&self, amount: u64, threshold: u64) -> bool {
+        self.timelock_delay > 0 && amount > threshold
+    }
+}
+
+/// A time-locked withdrawal, seeds `[b"pending", owner, mint, nonce]`. Holds
+/// `amount` off the owner's entry until executed or cancelled.
+#[account]
+#[derive(InitSpace)]
+pub struct PendingWithdrawal {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub unlock_at: i64,
+    pub nonce: u64,
+    pub bump: u8,
+}
+
+/// The all-users counter behind `Config.global_rate_quota`, seeds
+/// `[b"global_stats"]`. Created by `initialize`.
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalStats {
+    pub window: i64,
+    pub count: u32,
+    pub bump: u8,
+}
+
+// ---------------------------------------------------------------- contexts
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(init, payer = authority, space = 8 + Config::INIT_SPACE, seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+    #[account(init, payer = authority, space = 8 + FeePot::INIT_SPACE, seeds = [b"fee_pot"], bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    #[account(init, payer = authority, space = 8 + SolVault::INIT_SPACE, seeds = [b"sol_vault"], bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(init, payer = authority, space = 8 + GlobalStats::INIT_SPACE, seeds = [b"global_stats"], bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(address = config.authority @ VaultError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(address = config.pending_authority @ VaultError::Unauthorized)]
+    pub pending_authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct SetMintPolicy<'info> {
+    #[account(mut, address = config.authority @ VaultError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed, payer = authority, space = 8 + MintPolicy::INIT_SPACE,
+        seeds = [b"mint_policy", mint.as_ref()], bump
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct SetTvlCap<'info> {
+    #[account(mut, address = config.authority @ VaultError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed, payer = authority, space = 8 + MintTotals::INIT_SPACE,
+        seeds = [b"mint_totals", mint.as_ref()], bump
+    )]
+    pub mint_totals: Account<'info, MintTotals>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetFee<'info> {
+    #[account(mut, address = config.authority @ VaultError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + MintTotals::INIT_SPACE,
+        seeds = [b"mint_totals", Pubkey::default().as_ref()], bump
+    )]
+    pub mint_totals: Account<'info, MintTotals>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump = vault_entry.bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    #[account(mut, seeds = [b"mint_totals", Pubkey::default().as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TransferInternalSol<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    /// CHECK: recipient identity only; funds stay inside the program ledger.
+    pub recipient: UncheckedAccount<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump = from_entry.bump
+    )]
+    pub from_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", recipient.key().as_ref(), Pubkey::default().as_ref()], bump
+    )]
+    pub to_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TransferInternalToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    /// CHECK: recipient identity only; tokens stay in the program's custody.
+    pub recipient: UncheckedAccount<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump = from_entry.bump
+    )]
+    pub from_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", recipient.key().as_ref(), mint.key().as_ref()], bump
+    )]
+    pub to_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: the mint's `MintPolicy` address; may be uninitialised, which
+    /// `check_mint_policy` reads as "no policy".
+    #[account(seeds = [b"mint_policy", mint.key().as_ref()], bump)]
+    pub mint_policy: UncheckedAccount<'info>,
+    #[account(mut, constraint = owner_token_account.mint == mint.key() @ VaultError::MintMismatch)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns the mint's custody account; never deserialised.
+    #[account(seeds = [b"token_vault", mint.key().as_ref()], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// Canonical custody: `vault_authority`'s ATA, created on first deposit.
+    #[account(
+        init_if_needed, payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + MintTotals::INIT_SPACE,
+        seeds = [b"mint_totals", mint.key().as_ref()], bump
+    )]
+    pub mint_totals: Account<'info, MintTotals>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, constraint = owner_token_account.mint == mint.key() @ VaultError::MintMismatch)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that signs SPL transfers out of the token vault.
+    #[account(seeds = [b"token_vault", mint.key().as_ref()], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump = vault_entry.bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    #[account(mut, seeds = [b"mint_totals", mint.key().as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Per-leg accounts arrive through `remaining_accounts`; see
+/// `deposit_multiple_tokens` for the group layout.
+#[derive(Accounts)]
+pub struct DepositMultipleTokens<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Per-leg accounts arrive through `remaining_accounts`; see
+/// `withdraw_multiple_tokens` for the group layouts.
+#[derive(Accounts)]
+pub struct WithdrawMultipleTokens<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Per-leg entries arrive through `remaining_accounts`; see
+/// `transfer_multiple_tokens_internal` for the group layout.
+#[derive(Accounts)]
+pub struct TransferMultipleTokensInternal<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    /// CHECK: recipient identity only; funds stay inside the program ledger.
+    pub recipient: UncheckedAccount<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey, delegate: Pubkey)]
+pub struct Approve<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + Allowance::INIT_SPACE,
+        seeds = [b"allow", owner.key().as_ref(), mint.as_ref(), delegate.as_ref()], bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Revoke<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut, close = owner, has_one = owner @ VaultError::Unauthorized,
+        seeds = [
+            b"allow",
+            owner.key().as_ref(),
+            allowance.mint.as_ref(),
+            allowance.delegate.as_ref(),
+        ],
+        bump = allowance.bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+}
+
+#[derive(Accounts)]
+pub struct DelegatedWithdrawSol<'info> {
+    #[account(mut)]
+    pub delegate: Signer<'info>,
+    /// CHECK: the balance owner; bound by the allowance and entry seeds.
+    pub owner: UncheckedAccount<'info>,
+    /// The owner's `UserStats`: its quota is charged alongside the delegate's,
+    /// and its withdrawal lock applies.
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub owner_stats: Account<'info, UserStats>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"allow", owner.key().as_ref(), Pubkey::default().as_ref(), delegate.key().as_ref()],
+        bump = allowance.bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump = vault_entry.bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    #[account(mut, seeds = [b"mint_totals", Pubkey::default().as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", delegate.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
 
 #[derive(Accounts)]
-pub struct DepositSol<'info> {
+pub struct DelegatedWithdrawToken<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub delegate: Signer<'info>,
+    /// CHECK: the balance owner; bound by the allowance and entry seeds.
+    pub owner: UncheckedAccount<'info>,
+    /// The owner's `UserStats`: its quota is charged alongside the delegate's,
+    /// and its withdrawal lock applies.
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub owner_stats: Account<'info, UserStats>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
     #[account(
-        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
-        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"allow", owner.key().as_ref(), mint.key().as_ref(), delegate.key().as_ref()],
+        bump = allowance.bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    #[account(mut, constraint = delegate_token_account.mint == mint.key() @ VaultError::MintMismatch)]
+    pub delegate_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that signs SPL transfers out of the token vault.
+    #[account(seeds = [b"token_vault", mint.key().as_ref()], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump = vault_entry.bump
     )]
     pub vault_entry: Account<'info, VaultEntry>,
+    #[account(mut, seeds = [b"mint_totals", mint.key().as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
     #[account(
-        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
-        seeds = [b"stats", owner.key().as_ref()], bump
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", delegate.key().as_ref()], bump
     )]
     pub user_stats: Account<'info, UserStats>,
-    /// CHECK: PDA that custodies native SOL; balance-only, never deserialised.
-    #[account(mut, seeds = [b"sol_vault"], bump)]
-    pub sol_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawSol<'info> {
+pub struct DelegatedTransferInternalSol<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub delegate: Signer<'info>,
+    /// CHECK: the balance owner; bound by the allowance and entry seeds.
+    pub owner: UncheckedAccount<'info>,
+    /// The owner's `UserStats`: its quota is charged alongside the delegate's,
+    /// and its withdrawal lock applies.
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub owner_stats: Account<'info, UserStats>,
+    /// CHECK: recipient identity only; funds stay inside the program ledger.
+    pub recipient: UncheckedAccount<'info>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
     #[account(
         mut, has_one = owner @ VaultError::Unauthorized,
-        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump = vault_entry.bump
+        seeds = [b"allow", owner.key().as_ref(), Pubkey::default().as_ref(), delegate.key().as_ref()],
+        bump = allowance.bump
     )]
-    pub vault_entry: Account<'info, VaultEntry>,
+    pub allowance: Account<'info, Allowance>,
     #[account(
-        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
-        seeds = [b"stats", owner.key().as_ref()], bump
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump = from_entry.bump
+    )]
+    pub from_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", recipient.key().as_ref(), Pubkey::default().as_ref()], bump
+    )]
+    pub to_entry: Account<'info, VaultEntry>,
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", delegate.key().as_ref()], bump
     )]
     pub user_stats: Account<'info, UserStats>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"sol_vault"], bump)]
-    pub sol_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct TransferInternalSol<'info> {
+pub struct DelegatedTransferInternalToken<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
-    /// CHECK: recipient identity only; funds stay inside the program ledger.
+    pub delegate: Signer<'info>,
+    /// CHECK: the balance owner; bound by the allowance and entry seeds.
+    pub owner: UncheckedAccount<'info>,
+    /// The owner's `UserStats`: its quota is charged alongside the delegate's,
+    /// and its withdrawal lock applies.
+    #[account(
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub owner_stats: Account<'info, UserStats>,
+    /// CHECK: recipient identity only; tokens stay in the program's custody.
     pub recipient: UncheckedAccount<'info>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
     #[account(
         mut, has_one = owner @ VaultError::Unauthorized,
-        seeds = [b"vault", owner.key().as_ref(), Pubkey::default().as_ref()], bump = from_entry.bump
+        seeds = [b"allow", owner.key().as_ref(), mint.key().as_ref(), delegate.key().as_ref()],
+        bump = allowance.bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump = from_entry.bump
     )]
     pub from_entry: Account<'info, VaultEntry>,
     #[account(
-        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
-        seeds = [b"vault", recipient.key().as_ref(), Pubkey::default().as_ref()], bump
+        init_if_needed, payer = delegate, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", recipient.key().as_ref(), mint.key().as_ref()], bump
     )]
     pub to_entry: Account<'info, VaultEntry>,
     #[account(
-        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
-        seeds = [b"stats", owner.key().as_ref()], bump
+        init_if_needed, payer = delegate, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", delegate.key().as_ref()], bump
     )]
     pub user_stats: Account<'info, UserStats>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"sol_vault"], bump)]
-    pub sol_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct DepositToken<'info> {
+pub struct SetDailyCap<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), vault_entry.mint.as_ref()], bump = vault_entry.bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+}
+
+#[derive(Accounts)]
+pub struct SetTimelockThreshold<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    #[account(
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), vault_entry.mint.as_ref()], bump = vault_entry.bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalTimelock<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey, amount: u64, nonce: u64)]
+pub struct RequestWithdrawal<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
-    pub mint: InterfaceAccount<'info, Mint>,
-    #[account(mut, constraint = owner_token_account.mint == mint.key() @ VaultError::MintMismatch)]
-    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut, constraint = vault_token_account.mint == mint.key() @ VaultError::MintMismatch)]
-    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
     #[account(
-        init_if_needed, payer = owner, space = 8 + VaultEntry::INIT_SPACE,
-        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump
+        mut, has_one = owner @ VaultError::Unauthorized,
+        seeds = [b"vault", owner.key().as_ref(), mint.as_ref()], bump = vault_entry.bump
     )]
     pub vault_entry: Account<'info, VaultEntry>,
+    #[account(
+        init, payer = owner, space = 8 + PendingWithdrawal::INIT_SPACE,
+        seeds = [b"pending", owner.key().as_ref(), mint.as_ref(), &nonce.to_le_bytes()], bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(
+        init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"stats", owner.key().as_ref()], bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawalSol<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut, close = owner, has_one = owner @ VaultError::Unauthorized,
+        constraint = pending_withdrawal.mint == Pubkey::default() @ VaultError::MintMismatch,
+        seeds = [
+            b"pending",
+            owner.key().as_ref(),
+            pending_withdrawal.mint.as_ref(),
+            &pending_withdrawal.nonce.to_le_bytes(),
+        ],
+        bump = pending_withdrawal.bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(mut, seeds = [b"mint_totals", Pubkey::default().as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
     #[account(
         init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
         seeds = [b"stats", owner.key().as_ref()], bump
     )]
     pub user_stats: Account<'info, UserStats>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"sol_vault"], bump)]
-    pub sol_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
-    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawToken<'info> {
+pub struct ExecuteWithdrawalToken<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, Config>,
     pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut, close = owner, has_one = owner @ VaultError::Unauthorized,
+        constraint = pending_withdrawal.mint == mint.key() @ VaultError::MintMismatch,
+        seeds = [
+            b"pending",
+            owner.key().as_ref(),
+            pending_withdrawal.mint.as_ref(),
+            &pending_withdrawal.nonce.to_le_bytes(),
+        ],
+        bump = pending_withdrawal.bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
     #[account(mut, constraint = owner_token_account.mint == mint.key() @ VaultError::MintMismatch)]
     pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut, constraint = vault_token_account.mint == mint.key() @ VaultError::MintMismatch)]
-    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
     /// CHECK: PDA that signs SPL transfers out of the token vault.
     #[account(seeds = [b"token_vault", mint.key().as_ref()], bump)]
     pub vault_authority: UncheckedAccount<'info>,
     #[account(
-        mut, has_one = owner @ VaultError::Unauthorized,
-        seeds = [b"vault", owner.key().as_ref(), mint.key().as_ref()], bump = vault_entry.bump
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
     )]
-    pub vault_entry: Account<'info, VaultEntry>,
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"mint_totals", mint.key().as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
     #[account(
         init_if_needed, payer = owner, space = 8 + UserStats::INIT_SPACE,
         seeds = [b"stats", owner.key().as_ref()], bump
     )]
     pub user_stats: Account<'info, UserStats>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"sol_vault"], bump)]
-    pub sol_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"global_stats"], bump = global_stats.bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
+    /// CHECK: Pyth SOL/USD price account; required only in oracle fee mode,
+    /// where `current_fee` pins it to `config.price_feed`.
+    pub price_feed: Option<UncheckedAccount<'info>>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct CancelWithdrawal<'info> {
+    /// The owner, or the guardian recorded in `owner_stats`.
+    #[account(mut)]
+    pub canceller: Signer<'info>,
+    /// CHECK: receives the request's rent; bound by `has_one`.
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: the owner's `UserStats` address, read for their guardian.
+    #[account(seeds = [b"stats", owner.key().as_ref()], bump)]
+    pub owner_stats: UncheckedAccount<'info>,
+    #[account(
+        mut, close = owner, has_one = owner @ VaultError::Unauthorized,
+        seeds = [
+            b"pending",
+            owner.key().as_ref(),
+            pending_withdrawal.mint.as_ref(),
+            &pending_withdrawal.nonce.to_le_bytes(),
+        ],
+        bump = pending_withdrawal.bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(
+        init_if_needed, payer = canceller, space = 8 + VaultEntry::INIT_SPACE,
+        seeds = [b"vault", owner.key().as_ref(), pending_withdrawal.mint.as_ref()], bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseVaultEntry<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut, close = owner, has_one = owner @ VaultError::Unauthorized,
+        constraint = vault_entry.amount == 0 @ VaultError::EntryNotEmpty,
+        seeds = [b"vault", owner.key().as_ref(), vault_entry.mint.as_ref()], bump = vault_entry.bump
+    )]
+    pub vault_entry: Account<'info, VaultEntry>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserStats<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, close = owner, seeds = [b"stats", owner.key().as_ref()], bump)]
+    pub user_stats: Account<'info, UserStats>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct AssertSolvency<'info> {
+    #[account(seeds = [b"mint_totals", mint.as_ref()], bump = mint_totals.bump)]
+    pub mint_totals: Account<'info, MintTotals>,
+    #[account(seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
+    #[account(seeds = [b"fee_pot"], bump = fee_pot.bump)]
+    pub fee_pot: Account<'info, FeePot>,
+    /// The mint's canonical custody ATA; checked in `assert_solvency`.
+    pub vault_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
 #[derive(Accounts)]
 pub struct CollectFees<'info> {
     #[account(mut)]
@@ -499,9 +3163,8 @@ pub struct CollectFees<'info> {
     /// CHECK: must equal the configured collector; enforced by `address`.
     #[account(mut, address = config.fee_collector @ VaultError::Unauthorized)]
     pub fee_collector: UncheckedAccount<'info>,
-    /// CHECK: SOL-custody PDA, balance-only.
-    #[account(mut, seeds = [b"sol_vault"], bump)]
-    pub sol_vault: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"sol_vault"], bump = sol_vault.bump)]
+    pub sol_vault: Account<'info, SolVault>,
     #[account(mut, seeds = [b"fee_pot"], bump = fee_pot.bump)]
     pub fee_pot: Account<'info, FeePot>,
 }
@@ -516,6 +3179,85 @@ pub struct Withdraw { pub owner: Pubkey, pub mint: Pubkey, pub amount: u64, pub
 pub struct InternalTransfer { pub from: Pubkey, pub to: Pubkey, pub mint: Pubkey, pub amount: u64 }
 #[event]
 pub struct FeesCollected { pub to: Pubkey, pub amount: u64 }
+#[event]
+pub struct VaultEntryClosed { pub owner: Pubkey, pub mint: Pubkey }
+/// Mirrors `BatchOperation(user, count)`; also carries the single batch fee.
+#[event]
+pub struct BatchOperation { pub owner: Pubkey, pub count: u8, pub fee: u64 }
+
+#[event]
+pub struct AuthorityProposed { pub authority: Pubkey, pub pending_authority: Pubkey }
+#[event]
+pub struct AuthorityTransferred { pub previous: Pubkey, pub authority: Pubkey }
+#[event]
+pub struct FeeCollectorUpdated { pub previous: Pubkey, pub fee_collector: Pubkey }
+#[event]
+pub struct PauseUpdated { pub paused: u8 }
+#[event]
+pub struct MintPolicyUpdated {
+    pub mint: Pubkey,
+    pub allowed: bool,
+    pub max_deposit: u64,
+    pub min_deposit: u64,
+}
+#[event]
+pub struct OpenModeUpdated { pub open_mode: bool }
+#[event]
+pub struct TvlCapUpdated { pub mint: Pubkey, pub cap: u64, pub total: u64 }
+#[event]
+pub struct RateLimitsUpdated { pub window_secs: i64, pub quota: u32, pub global_quota: u32 }
+#[event]
+pub struct RateExemptionUpdated { pub wallet: Pubkey, pub exempt: bool }
+#[event]
+pub struct AllowanceUpdated {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub delegate: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+#[event]
+pub struct AllowanceSpent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub delegate: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+#[event]
+pub struct DailyCapUpdated { pub owner: Pubkey, pub mint: Pubkey, pub cap: u64, pub effective_at: i64 }
+#[event]
+pub struct TimelockUpdated {
+    pub owner: Pubkey,
+    pub delay: i64,
+    pub guardian: Pubkey,
+    pub effective_at: i64,
+}
+#[event]
+pub struct TimelockThresholdUpdated { pub owner: Pubkey, pub mint: Pubkey, pub threshold: u64, pub effective_at: i64 }
+#[event]
+pub struct WithdrawalRequested {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    pub unlock_at: i64,
+}
+#[event]
+pub struct WithdrawalCancelled {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    pub by: Pubkey,
+}
+#[event]
+pub struct SolvencyReport {
+    pub mint: Pubkey,
+    pub liabilities: u64,
+    pub custody: u64,
+    pub solvent: bool,
+}
 
 #[error_code]
 pub enum VaultError {
@@ -539,4 +3281,439 @@ pub enum VaultError {
     MintMismatch,
     #[msg("Nothing to collect")]
     NothingToCollect,
+    #[msg("Batch must contain between 1 and MAX_BATCH_SIZE legs that fit in one transaction")]
+    InvalidBatchSize,
+    #[msg("Remaining accounts do not match the batch layout")]
+    BatchAccountsMismatch,
+    #[msg("Duplicate mint in batch")]
+    DuplicateMint,
+    #[msg("Vault entry does not match the owner and mint")]
+    InvalidVaultEntry,
+    #[msg("Array length mismatch")]
+    ArrayLengthMismatch,
+    #[msg("Vault authority does not match the mint")]
+    InvalidVaultAuthority,
+    #[msg("Invalid recipient")]
+    InvalidRecipient,
+    #[msg("Oracle fee mode requires the configured price feed account")]
+    PriceFeedMissing,
+    #[msg("Price feed account does not match the configured feed")]
+    PriceFeedMismatch,
+    #[msg("Price feed account data is malformed")]
+    InvalidPriceFeed,
+    #[msg("Invalid oracle fee configuration")]
+    InvalidOracleConfig,
+    #[msg("SOL custody cannot cover this without breaching its rent floor")]
+    CustodyShortfall,
+    #[msg("Token account is not the vault's canonical custody account for this mint")]
+    InvalidCustodyAccount,
+    #[msg("Non-transferable mints are not supported")]
+    NonTransferableMint,
+    #[msg("Confidential-transfer mints are not supported")]
+    ConfidentialMint,
+    #[msg("Mints with a permanent delegate are not supported")]
+    PermanentDelegateMint,
+    #[msg("Transfer-hook mints must use the single-mint instructions")]
+    TransferHookInBatch,
+    #[msg("Vault entry still holds a balance")]
+    EntryNotEmpty,
+    #[msg("Rate-limit window is still open")]
+    StatsWindowOpen,
+    #[msg("This instruction class is paused")]
+    Paused,
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
+    #[msg("Mint is not allowed")]
+    MintNotAllowed,
+    #[msg("Deposit is below the mint's minimum")]
+    BelowMinDeposit,
+    #[msg("Deposit is above the mint's per-deposit cap")]
+    AboveMaxDeposit,
+    #[msg("Invalid mint policy")]
+    InvalidMintPolicy,
+    #[msg("Deposit would exceed the mint's TVL cap")]
+    TvlCapExceeded,
+    #[msg("Mint totals account does not match the mint")]
+    InvalidMintTotals,
+    #[msg("Custody holds less than the ledger owes")]
+    Insolvent,
+    #[msg("Rate-limit window and quota must be positive")]
+    InvalidRateLimit,
+    #[msg("Rate-limit exemption list is full")]
+    RateExemptListFull,
+    #[msg("Global rate limit exceeded")]
+    GlobalRateLimitExceeded,
+    #[msg("Invalid delegate")]
+    InvalidDelegate,
+    #[msg("Allowance has expired")]
+    AllowanceExpired,
+    #[msg("Amount exceeds the remaining allowance")]
+    AllowanceExceeded,
+    #[msg("Invalid withdrawal timelock")]
+    InvalidTimelock,
+    #[msg("Amount is above the timelock threshold; use request_withdrawal")]
+    WithdrawalTimelocked,
+    #[msg("Pending withdrawal is still locked")]
+    WithdrawalLocked,
+    #[msg("Withdrawal timelock is active")]
+    TimelockActive,
+    #[msg("Outflow would exceed the entry's daily cap")]
+    DailyCapExceeded,
+    #[msg("Entry has a daily cap set or scheduled")]
+    DailyCapActive,
+}
+
+// ---------------------------------------------------------------- tests
+//
+// Host-side (`cargo test`). The oracle fee is pinned against a synthetic
+// `PriceUpdateV2` account laid out byte-for-byte like the Pyth receiver's.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_780_000_000;
+
+    /// SOL at $150.00000000 (expo -8), conf $0.075 = 5 bps, published 10s ago.
+    fn fixture(full: bool) -> Vec<u8> {
+        let mut d = PYTH_PRICE_UPDATE_DISCRIMINATOR.to_vec();
+        d.extend_from_slice(&[7; 32]); // write_authority
+        if full {
+            d.push(1);
+        } else {
+            d.extend_from_slice(&[0, 3]);
+        }
+        d.extend_from_slice(&[9; 32]); // feed_id
+        d.extend_from_slice(&15_000_000_000i64.to_le_bytes());
+        d.extend_from_slice(&7_500_000u64.to_le_bytes());
+        d.extend_from_slice(&(-8i32).to_le_bytes());
+        d.extend_from_slice(&(NOW - 10).to_le_bytes());
+        d.extend_from_slice(&(NOW - 11).to_le_bytes()); // prev_publish_time
+        d.extend_from_slice(&[0; 16]); // ema_price, ema_conf
+        d.extend_from_slice(&[0; 8]); // posted_slot
+        d
+    }
+
+    #[test]
+    fn discriminator_matches_the_anchor_account_name() {
+        let h = anchor_lang::solana_program::hash::hash(b"account:PriceUpdateV2");
+        assert_eq!(h.to_bytes()[..8], PYTH_PRICE_UPDATE_DISCRIMINATOR);
+    }
+
+    #[test]
+    fn parses_the_fixture() {
+        let p = PythPrice::parse(&fixture(true)).unwrap();
+        assert_eq!(p.price, 15_000_000_000);
+        assert_eq!(p.conf, 7_500_000);
+        assert_eq!(p.exponent, -8);
+        assert_eq!(p.publish_time, NOW - 10);
+        assert!(p.fully_verified);
+        // The Partial encoding is one byte longer; the offsets must follow it.
+        let partial = PythPrice::parse(&fixture(false)).unwrap();
+        assert_eq!((partial.price, partial.fully_verified), (p.price, false));
+    }
+
+    #[test]
+    fn ten_cents_at_150_usd_is_666_666_lamports() {
+        // $0.10 / $150 = 0.000666... SOL, truncated like the EVM division.
+        let p = PythPrice::parse(&fixture(true)).unwrap();
+        assert_eq!(p.fee_lamports(USD_FEE_CENTS, NOW, 60, 100), Some(666_666));
+    }
+
+    #[test]
+    fn unusable_prices_fall_back() {
+        let p = PythPrice::parse(&fixture(true)).unwrap();
+        assert_eq!(p.fee_lamports(USD_FEE_CENTS, NOW, 5, 100), None, "stale");
+        assert_eq!(p.fee_lamports(USD_FEE_CENTS, NOW, 60, 4), None, "conf too wide");
+        assert_eq!(p.fee_lamports(USD_FEE_CENTS, NOW - 20, 60, 100), None, "from the future");
+        let partial = PythPrice::parse(&fixture(false)).unwrap();
+        assert_eq!(partial.fee_lamports(USD_FEE_CENTS, NOW, 60, 100), None, "unverified");
+        let negative = PythPrice { price: -1, ..p };
+        assert_eq!(negative.fee_lamports(USD_FEE_CENTS, NOW, 60, 100), None);
+    }
+
+    #[test]
+    fn malformed_accounts_are_rejected() {
+        let mut d = fixture(true);
+        d[0] ^= 1;
+        assert!(PythPrice::parse(&d).is_err());
+        assert!(PythPrice::parse(&fixture(true)[..60]).is_err());
+    }
+
+    /// A Token-2022 mint account carrying `extensions`, as the token program
+    /// would lay it out.
+    fn mint_2022(extensions: &[ExtensionType]) -> Vec<u8> {
+        use spl_token_2022::extension::{
+            non_transferable::NonTransferable, transfer_fee::TransferFeeConfig,
+            transfer_hook::TransferHook, BaseStateWithExtensionsMut, StateWithExtensionsMut,
+        };
+        use spl_token_2022::state::Mint as Mint2022;
+        let len = ExtensionType::try_calculate_account_len::<Mint2022>(extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Mint2022>::unpack_uninitialized(&mut data).unwrap();
+        for extension in extensions {
+            match extension {
+                ExtensionType::NonTransferable => {
+                    state.init_extension::<NonTransferable>(true).unwrap();
+                }
+                ExtensionType::TransferFeeConfig => {
+                    state.init_extension::<TransferFeeConfig>(true).unwrap();
+                }
+                ExtensionType::TransferHook => {
+                    let hook = state.init_extension::<TransferHook>(true).unwrap();
+                    hook.program_id = Some(Pubkey::new_from_array([4; 32])).try_into().unwrap();
+                }
+                other => panic!("fixture does not build {other:?}"),
+            }
+        }
+        state.base = Mint2022 { decimals: 6, is_initialized: true, ..Default::default() };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    fn inspect(owner: Pubkey, mut data: Vec<u8>) -> Result<bool> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        inspect_mint(&info)
+    }
+
+    #[test]
+    fn plain_and_fee_mints_pass_without_a_hook() {
+        assert!(!inspect(spl_token_2022::ID, mint_2022(&[])).unwrap());
+        assert!(!inspect(spl_token_2022::ID, mint_2022(&[ExtensionType::TransferFeeConfig])).unwrap());
+        // Legacy SPL mints are never inspected.
+        assert!(!inspect(anchor_spl::token::ID, vec![0; 82]).unwrap());
+    }
+
+    #[test]
+    fn transfer_hook_mints_are_reported() {
+        assert!(inspect(spl_token_2022::ID, mint_2022(&[ExtensionType::TransferHook])).unwrap());
+    }
+
+    #[test]
+    fn non_transferable_mints_are_refused() {
+        let err = inspect(spl_token_2022::ID, mint_2022(&[ExtensionType::NonTransferable]));
+        assert_eq!(err.unwrap_err(), VaultError::NonTransferableMint.into());
+    }
+
+    #[test]
+    fn only_what_custody_received_is_credited() {
+        assert_eq!(received_delta(1_000, 1_990).unwrap(), 990);
+        assert!(received_delta(1_000, 1_000).is_err());
+    }
+
+    #[test]
+    fn positive_exponents_scale_down() {
+        // A positive exponent moves the scale into the divisor; it must not panic.
+        let p = PythPrice { exponent: 9, ..PythPrice::parse(&fixture(true)).unwrap() };
+        assert_eq!(p.fee_lamports(USD_FEE_CENTS, NOW, 60, 100), Some(0));
+    }
+
+    #[test]
+    fn quota_is_spent_by_weight_and_resets_per_window() {
+        let (mut window, mut count) = (0i64, 0u32);
+        let w = window_start(1_000_030, 60);
+        assert_eq!(w, 1_000_020);
+        assert!(take_quota(&mut window, &mut count, w, 1, 10));
+        // A 25-leg batch does not fit in the 9 units left.
+        assert!(!take_quota(&mut window, &mut count, w, 25, 10));
+        assert_eq!(count, 1);
+        assert!(take_quota(&mut window, &mut count, w, 9, 10));
+        assert!(!take_quota(&mut window, &mut count, w, 1, 10));
+        assert!(take_quota(&mut window, &mut count, w + 60, 10, 10));
+        assert_eq!((window, count), (w + 60, 10));
+    }
+
+    #[test]
+    fn delegated_outflows_spend_the_owners_quota() {
+        let mut config = Config::try_deserialize_unchecked(&mut &[0u8; 8 + Config::INIT_SPACE][..]).unwrap();
+        config.rate_window_secs = 60;
+        config.rate_quota = 2;
+        let owner = Pubkey::new_unique();
+        let mut owner_stats = UserStats::try_deserialize_unchecked(&mut &[0u8; 8 + UserStats::INIT_SPACE][..])
+            .unwrap();
+        let clock = Clock { unix_timestamp: NOW, ..Clock::default() };
+        // Two delegates, one call each, use up the owner's quota; a third
+        // delegate with a fresh quota of its own still gets nothing.
+        rate_limit_owner(&config, &mut owner_stats, &owner, 1, &clock).unwrap();
+        rate_limit_owner(&config, &mut owner_stats, &owner, 1, &clock).unwrap();
+        assert_eq!(
+            rate_limit_owner(&config, &mut owner_stats, &owner, 1, &clock).unwrap_err(),
+            VaultError::RateLimitExceeded.into()
+        );
+        // An exempt owner is not limited.
+        config.rate_exempt.push(owner);
+        rate_limit_owner(&config, &mut owner_stats, &owner, 1, &clock).unwrap();
+    }
+
+    #[test]
+    fn allowances_draw_down_until_expiry() {
+        let mut a = Allowance {
+            owner: Pubkey::new_unique(),
+            mint: Pubkey::default(),
+            delegate: Pubkey::new_unique(),
+            amount: 100,
+            expires_at: NOW,
+            bump: 0,
+        };
+        a.spend(60, NOW - 1).unwrap();
+        assert_eq!(a.spend(41, NOW - 1).unwrap_err(), VaultError::AllowanceExceeded.into());
+        assert_eq!(a.spend(1, NOW).unwrap_err(), VaultError::AllowanceExpired.into());
+        assert_eq!(a.amount, 40);
+        a.expires_at = 0;
+        a.spend(40, NOW + 1_000_000).unwrap();
+    }
+
+    #[test]
+    fn timelock_tightens_at_once_and_loosens_after_the_delay() {
+        let guardian = Pubkey::new_unique();
+        let mut st = UserStats::try_deserialize_unchecked(&mut &[0u8; 8 + UserStats::INIT_SPACE][..])
+            .unwrap();
+        assert!(!st.requires_timelock(u64::MAX, 0));
+        assert_eq!(st.schedule_timelock(3_600, guardian, NOW), NOW);
+        assert!(st.requires_timelock(1_001, 1_000) && !st.requires_timelock(1_000, 1_000));
+        // Switching it off is a loosening: it waits out the hour.
+        assert_eq!(st.schedule_timelock(0, guardian, NOW), NOW + 3_600);
+        st.settle_timelock(NOW + 3_599);
+        assert!(st.requires_timelock(1_001, 1_000));
+        st.settle_timelock(NOW + 3_600);
+        assert!(!st.requires_timelock(u64::MAX, 0));
+    }
+
+    #[test]
+    fn timelock_thresholds_are_per_entry_and_rise_only_after_the_delay() {
+        let mut usdc = VaultEntry::try_deserialize_unchecked(&mut &[0u8; 8 + VaultEntry::INIT_SPACE][..])
+            .unwrap();
+        let mut sol = usdc.clone();
+        // 1,000 USDC (6 decimals) and 1 SOL (9 decimals): one raw number could not
+        // express both.
+        assert_eq!(usdc.schedule_threshold(1_000_000_000, 0, NOW), NOW);
+        assert_eq!(sol.schedule_threshold(1_000_000_000, 0, NOW), NOW);
+        assert_eq!(usdc.schedule_threshold(500_000_000, 3_600, NOW), NOW);
+        assert_eq!(sol.timelock_threshold, 1_000_000_000);
+        // Raising waits the owner's delay.
+        assert_eq!(usdc.schedule_threshold(2_000_000_000, 3_600, NOW), NOW + 3_600);
+        usdc.settle_threshold(NOW + 3_599);
+        assert_eq!(usdc.timelock_threshold, 500_000_000);
+        usdc.settle_threshold(NOW + 3_600);
+        assert_eq!(usdc.timelock_threshold, 2_000_000_000);
+    }
+
+    #[test]
+    fn daily_cap_limits_outflow_over_a_rolling_day() {
+        let day = OUTFLOW_WINDOW_SECONDS;
+        let mut e = VaultEntry::try_deserialize_unchecked(&mut &[0u8; 8 + VaultEntry::INIT_SPACE][..])
+            .unwrap();
+        e.amount = 1_000;
+        e.debit(300, NOW).unwrap();
+        // Capped mid-window: the 300 already out counts.
+        assert_eq!(e.schedule_daily_cap(400, NOW), NOW);
+        assert_eq!(e.debit(101, NOW).unwrap_err(), VaultError::DailyCapExceeded.into());
+        e.debit(100, NOW).unwrap();
+        e.debit(400, NOW + 2 * day).unwrap();
+        // Raising waits a day; until then the old cap holds.
+        assert_eq!(e.schedule_daily_cap(0, NOW + 2 * day), NOW + 3 * day);
+        assert!(e.debit(1, NOW + 2 * day).is_err());
+        e.debit(200, NOW + 3 * day).unwrap();
+        assert_eq!(e.amount, 0);
+    }
+
+    #[test]
+    fn daily_cap_does_not_reset_at_the_window_boundary() {
+        let day = OUTFLOW_WINDOW_SECONDS;
+        let mut e = VaultEntry::try_deserialize_unchecked(&mut &[0u8; 8 + VaultEntry::INIT_SPACE][..])
+            .unwrap();
+        e.amount = 10_000;
+        e.schedule_daily_cap(1_000, NOW);
+        let midnight = window_start(NOW, day) + day;
+        e.debit(1_000, midnight - 1).unwrap();
+        // A fixed UTC day would allow another 1,000 a second later.
+        assert!(e.debit(1, midnight + 1).is_err());
+        // Nothing comes back early: a full day on, that 1,000 still counts.
+        assert!(e.debit(1, midnight - 1 + day).is_err());
+        // Once its bucket has left the window, the whole cap is free again.
+        e.debit(1_000, midnight + day).unwrap();
+    }
+
+    #[test]
+    fn no_24_hour_span_moves_more_than_the_cap() {
+        let day = OUTFLOW_WINDOW_SECONDS;
+        let cap = 1_000;
+        let mut e = VaultEntry::try_deserialize_unchecked(&mut &[0u8; 8 + VaultEntry::INIT_SPACE][..])
+            .unwrap();
+        e.amount = u64::MAX;
+        e.schedule_daily_cap(cap, NOW);
+        // Spend greedily, in uneven amounts and steps, over four days.
+        let mut sent: Vec<(i64, u64)> = Vec::new();
+        let mut t = NOW;
+        while t < NOW + 4 * day {
+            let amount = 1 + (t as u64 % 7) * 60;
+            if e.debit(amount, t).is_ok() {
+                sent.push((t, amount));
+            }
+            t += 7 * 60 + 13;
+        }
+        for &(end, _) in &sent {
+            let span: u64 = sent.iter().filter(|(t, _)| (end - day..=end).contains(t)).map(|(_, a)| a).sum();
+            assert!(span <= cap, "{span} left in the day ending at {end}");
+        }
+        // Capped, not frozen: most of four days' allowance got through.
+        assert!(sent.iter().map(|(_, a)| a).sum::<u64>() > 3 * cap);
+    }
+
+    #[test]
+    fn a_due_cap_raise_settles_before_the_next_change() {
+        let mut e = VaultEntry::try_deserialize_unchecked(&mut &[0u8; 8 + VaultEntry::INIT_SPACE][..])
+            .unwrap();
+        e.schedule_daily_cap(100, NOW);
+        e.schedule_daily_cap(1_000, NOW);
+        // Once the raise to 1,000 is due, 500 is a lowering and applies at once.
+        let due = NOW + DAILY_CAP_RAISE_DELAY;
+        assert_eq!(e.schedule_daily_cap(500, due), due);
+        assert_eq!((e.daily_cap, e.pending_cap_at), (500, 0));
+        assert!(e.caps_outflow());
+        // Removing the cap is a raise; the entry stays unclosable until it lands.
+        e.schedule_daily_cap(0, due);
+        e.settle_daily_cap(due + DAILY_CAP_RAISE_DELAY - 1);
+        assert!(e.caps_outflow());
+        e.settle_daily_cap(due + DAILY_CAP_RAISE_DELAY);
+        assert!(!e.caps_outflow());
+    }
+
+    #[test]
+    fn tvl_cap_bounds_deposits_but_not_withdrawals() {
+        let mut t = MintTotals { mint: Pubkey::default(), total: 0, cap: 1_000, bump: 0 };
+        t.credit(1_000).unwrap();
+        assert_eq!(t.credit(1).unwrap_err(), VaultError::TvlCapExceeded.into());
+        assert_eq!(t.total, 1_000);
+        // Lowered below the total: withdrawals still go through.
+        t.cap = 400;
+        t.debit(700).unwrap();
+        assert_eq!(t.credit(101).unwrap_err(), VaultError::TvlCapExceeded.into());
+        t.cap = 0;
+        t.credit(1_000_000).unwrap();
+        assert!(t.debit(1_000_301).is_err());
+    }
+    #[test]
+    fn batches_stop_at_what_one_transaction_can_lock() {
+        let legs = |n: usize| vec![1u64; n];
+        // Deposits: 7 accounts a leg, so 6 legs fit and 7 do not.
+        validate_batch(&legs(6), 6 * DEPOSIT_BATCH_ACCOUNTS, 6 * DEPOSIT_BATCH_ACCOUNTS).unwrap();
+        assert_eq!(
+            validate_batch(&legs(7), 7 * DEPOSIT_BATCH_ACCOUNTS, 7 * DEPOSIT_BATCH_ACCOUNTS).unwrap_err(),
+            VaultError::InvalidBatchSize.into()
+        );
+        // SPL withdrawals: 8 legs.
+        validate_batch(&legs(8), 8 * WITHDRAW_BATCH_TOKEN_ACCOUNTS, 8 * WITHDRAW_BATCH_TOKEN_ACCOUNTS).unwrap();
+        assert!(validate_batch(&legs(9), 9 * WITHDRAW_BATCH_TOKEN_ACCOUNTS, 9 * WITHDRAW_BATCH_TOKEN_ACCOUNTS).is_err());
+        // Two-account legs: 24, one short of the EVM MAX_BATCH_SIZE.
+        assert_eq!(
+            (MAX_DEPOSIT_BATCH_LEGS, MAX_WITHDRAW_TOKEN_BATCH_LEGS, MAX_WITHDRAW_SOL_BATCH_LEGS, MAX_INTERNAL_BATCH_LEGS),
+            (6, 8, 24, 24)
+        );
+        assert_eq!(MAX_BATCH_SIZE, 25);
+        validate_batch(&legs(24), 48, 48).unwrap();
+        assert!(validate_batch(&legs(25), 50, 50).is_err());
+    }
 }